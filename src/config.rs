@@ -0,0 +1,66 @@
+use crate::container::ContainerConfig;
+use crate::nodes::{CircuitBreakerConfig, NodeConfig};
+use crate::presets::Preset;
+use crate::queue::QueueConfig;
+use crate::webhooks::WebhookConfig;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+
+// Ruta del archivo de configuración si no se indica `WEBODM_CONFIG`.
+const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+// Configuración del servicio, cargada al iniciar desde un archivo JSON.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub presets: Vec<Preset>,
+    // Segundos que se espera a que NodeODM responda en `/info` tras iniciarlo.
+    pub node_ready_timeout_secs: u64,
+    pub container: ContainerConfig,
+    // Nodos NodeODM adicionales entre los que se reparten los trabajos.
+    pub nodes: Vec<NodeConfig>,
+    pub circuit_breaker: CircuitBreakerConfig,
+    pub queue: QueueConfig,
+    // Errores consecutivos al consultar una tarea antes de dar su nodo por perdido.
+    pub max_poll_failures: u32,
+    // Directorio de datos del servicio (imágenes conservadas de cada trabajo).
+    pub data_dir: String,
+    pub webhooks: WebhookConfig,
+    // URL pública del servicio, usada en las URLs de resultados de los webhooks.
+    pub public_url: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            presets: Vec::new(),
+            node_ready_timeout_secs: 120,
+            container: ContainerConfig::default(),
+            nodes: Vec::new(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            queue: QueueConfig::default(),
+            max_poll_failures: 6,
+            data_dir: "data".to_string(),
+            webhooks: WebhookConfig::default(),
+            public_url: "http://127.0.0.1:3001".to_string(),
+        }
+    }
+}
+
+impl Config {
+    // Lee la configuración de `WEBODM_CONFIG` (o `config.json`). Si el archivo
+    // no existe se usan los valores por defecto.
+    pub fn load() -> io::Result<Config> {
+        let path = std::env::var("WEBODM_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                println!("No se encontró {}, usando la configuración por defecto.", path);
+                Ok(Config::default())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
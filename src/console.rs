@@ -0,0 +1,165 @@
+use crate::pipeline::Pipeline;
+use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::stream;
+use serde::Deserialize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+// Cada cuánto revisa el stream SSE si hay líneas nuevas en el log.
+const STREAM_INTERVAL: Duration = Duration::from_secs(1);
+
+// Consola de ODM de un trabajo, guardada en disco para poder leerla aunque la
+// tarea ya no exista en el nodo. Cada línea del archivo es una línea de la consola.
+
+// Empieza un log vacío para una tarea nueva (o reiniciada) en NodeODM.
+pub fn reset(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::File::create(path).map(|_| ())
+}
+
+pub fn append(path: &Path, lines: &[String]) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for line in lines {
+        writeln!(file, "{}", line)?;
+    }
+    Ok(())
+}
+
+// Lee todas las líneas guardadas; un log que todavía no existe está vacío.
+pub fn read(path: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content.lines().map(str::to_owned).collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+// Lee las líneas completas que se agregaron a partir del byte `position` y
+// devuelve la nueva posición. Si el archivo quedó más corto (el log se reinició)
+// devuelve `None`.
+fn read_from(path: &Path, position: u64) -> io::Result<Option<(Vec<String>, u64)>> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Some((Vec::new(), position))),
+        Err(err) => return Err(err),
+    };
+    if file.metadata()?.len() < position {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(position))?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    // Una línea a medio escribir se lee en la próxima vuelta.
+    let complete = content.iter().rposition(|&b| b == b'\n').map_or(0, |end| end + 1);
+    let lines = String::from_utf8_lossy(&content[..complete]).lines().map(str::to_owned).collect();
+    Ok(Some((lines, position + complete as u64)))
+}
+
+#[derive(Deserialize)]
+pub struct LogQuery {
+    // Igual que en `/task/{uuid}/output`: línea inicial, o las últimas `-line` si es negativo.
+    #[serde(default)]
+    line: i64,
+    // Máximo de líneas a devolver.
+    limit: Option<usize>,
+}
+
+// GET /jobs/{id}/log — arreglo JSON con las líneas guardadas de la consola.
+pub async fn get_log(pipeline: web::Data<Pipeline>, path: web::Path<String>, query: web::Query<LogQuery>) -> HttpResponse {
+    let id = path.into_inner();
+    if pipeline.jobs.get(&id).is_none() {
+        return HttpResponse::NotFound().body("Trabajo no encontrado");
+    }
+    let lines = match read(&pipeline.console_path(&id)) {
+        Ok(lines) => lines,
+        Err(err) => return HttpResponse::InternalServerError().body(format!("Error al leer el log: {}", err)),
+    };
+
+    let start = if query.line < 0 {
+        lines.len().saturating_sub(query.line.unsigned_abs() as usize)
+    } else {
+        (query.line as usize).min(lines.len())
+    };
+    let end = query.limit.map_or(lines.len(), |limit| start.saturating_add(limit).min(lines.len()));
+    HttpResponse::Ok().json(&lines[start..end])
+}
+
+// GET /jobs/{id}/log/stream — sigue la consola con Server-Sent Events. Cada línea
+// es un evento cuyo `id` es el número de líneas enviadas, así que un cliente que
+// se reconecta con `Last-Event-ID` (o `?line=N`) continúa donde se quedó. Cuando el
+// trabajo termina se envía un evento `end` con su estado y se cierra el stream.
+pub async fn stream_log(
+    pipeline: web::Data<Pipeline>,
+    path: web::Path<String>,
+    query: web::Query<LogQuery>,
+    request: HttpRequest,
+) -> HttpResponse {
+    let id = path.into_inner();
+    if pipeline.jobs.get(&id).is_none() {
+        return HttpResponse::NotFound().body("Trabajo no encontrado");
+    }
+    let skip = request
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(query.line.max(0) as usize);
+
+    // Estado del stream: líneas leídas del log, byte hasta el que se leyó (para
+    // leer en cada vuelta solo lo que se agregó) y líneas que el cliente ya tiene.
+    let events = stream::unfold(Some((0usize, 0u64, skip)), move |state| {
+        let pipeline = pipeline.clone();
+        let id = id.clone();
+        async move {
+            let (mut line, mut position, mut skip) = state?;
+            loop {
+                // Leer el estado antes que el log: si ya terminó, el log está completo.
+                let job = pipeline.jobs.get(&id);
+                match read_from(&pipeline.console_path(&id), position) {
+                    Ok(Some((lines, next))) => {
+                        position = next;
+                        let mut chunk = String::new();
+                        for text in lines {
+                            line += 1;
+                            if line > skip {
+                                // Un `\r` (barras de progreso de ODM) cortaría el evento.
+                                chunk.push_str(&format!("id: {}\ndata: {}\n\n", line, text.replace('\r', "")));
+                            }
+                        }
+                        if !chunk.is_empty() {
+                            return Some((Ok::<_, actix_web::Error>(Bytes::from(chunk)), Some((line, position, skip))));
+                        }
+                    }
+                    // El log se reinició con una tarea nueva: enviarlo desde el principio.
+                    Ok(None) => {
+                        line = 0;
+                        position = 0;
+                        skip = 0;
+                        continue;
+                    }
+                    Err(err) => println!("Error al leer el log del trabajo {}: {}", id, err),
+                }
+                match job {
+                    Some(job) if !job.state.is_terminal() => tokio::time::sleep(STREAM_INTERVAL).await,
+                    // Trabajo terminado (o eliminado): avisar y cerrar.
+                    job => {
+                        let state = job.map_or("deleted", |j| j.state.name());
+                        let event = format!("event: end\ndata: {}\n\n", state);
+                        return Some((Ok(Bytes::from(event)), None));
+                    }
+                }
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((CACHE_CONTROL, HeaderValue::from_static("no-cache")))
+        .streaming(events)
+}
@@ -0,0 +1,267 @@
+use crate::nodes::{NodeConfig, NodeLease};
+use crate::runtime::{self, ContainerRuntime, RunSpec, RuntimeError, RuntimeKind};
+use serde::Deserialize;
+use std::io;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use webodm_client::nodeodm::NodeOdmClient;
+
+// Cómo se obtiene el contenedor de NodeODM para cada trabajo.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerMode {
+    // Un solo contenedor de larga duración compartido por todos los trabajos.
+    Shared,
+    // Un contenedor nuevo por trabajo, detenido al terminar.
+    PerJob,
+    // Sin contenedor local; solo se usan los nodos registrados en `nodes`.
+    None,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ContainerConfig {
+    pub mode: ContainerMode,
+    pub runtime: RuntimeKind,
+    // URL del NodeODM cuando `runtime` es `external`.
+    pub external_url: String,
+    pub image: String,
+    // Nombre del contenedor compartido; permite adoptarlo si ya existe.
+    pub name: String,
+    // Puerto del contenedor compartido. En modo por trabajo se elige un puerto libre.
+    pub port: u16,
+    // Máximo de trabajos simultáneos en el contenedor compartido; `None` es sin límite.
+    pub max_tasks: Option<usize>,
+    pub health_check_interval_secs: u64,
+    // Fallos consecutivos de `/info` antes de reiniciar el contenedor compartido.
+    pub max_health_failures: u32,
+}
+
+impl Default for ContainerConfig {
+    fn default() -> Self {
+        ContainerConfig {
+            mode: ContainerMode::Shared,
+            runtime: RuntimeKind::Docker,
+            external_url: "http://localhost:3000".to_string(),
+            image: "opendronemap/nodeodm".to_string(),
+            name: "webodm-nodeodm".to_string(),
+            port: 3000,
+            max_tasks: None,
+            health_check_interval_secs: 30,
+            max_health_failures: 3,
+        }
+    }
+}
+
+// Intentos de crear un contenedor por trabajo si el puerto elegido ya fue tomado.
+const PORT_ATTEMPTS: usize = 3;
+
+// Docker reporta "port is already allocated"; Podman, "address already in use".
+fn is_port_conflict(message: &str) -> bool {
+    message.contains("already allocated") || message.contains("address already in use")
+}
+
+// Pide al sistema operativo un puerto libre en el host.
+fn free_port() -> io::Result<u16> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    Ok(listener.local_addr()?.port())
+}
+
+// Contenedor compartido: su ID y si lo iniciamos nosotros (y por tanto lo detenemos al salir).
+#[derive(Default)]
+struct SharedContainer {
+    id: Option<String>,
+    owned: bool,
+}
+
+// Administra el ciclo de vida de los contenedores de NodeODM.
+#[derive(Clone)]
+pub struct ContainerManager {
+    config: ContainerConfig,
+    runtime: Arc<dyn ContainerRuntime>,
+    ready_timeout: Duration,
+    shared: Arc<Mutex<SharedContainer>>,
+}
+
+impl ContainerManager {
+    pub fn new(config: ContainerConfig, ready_timeout: Duration) -> Self {
+        let runtime = runtime::from_config(&config.runtime, &config.external_url).into();
+        ContainerManager { config, runtime, ready_timeout, shared: Arc::default() }
+    }
+
+    pub fn mode(&self) -> ContainerMode {
+        self.config.mode
+    }
+
+    fn node(&self) -> NodeOdmClient {
+        NodeOdmClient::new(&self.runtime.node_url(self.config.port))
+    }
+
+    // Nodo `local` que se registra en el planificador cuando hay contenedor compartido.
+    pub fn shared_node(&self) -> Option<NodeConfig> {
+        (self.config.mode == ContainerMode::Shared).then(|| NodeConfig {
+            name: "local".to_string(),
+            url: self.runtime.node_url(self.config.port),
+            token: None,
+            max_tasks: self.config.max_tasks,
+        })
+    }
+
+    // Inicia (o adopta) el contenedor compartido y lanza la verificación periódica
+    // de salud. En modo por trabajo no hace nada.
+    pub async fn start(&self) -> Result<(), String> {
+        if self.config.mode != ContainerMode::Shared {
+            return Ok(());
+        }
+        self.ensure_shared().await?;
+        self.node().wait_until_ready(self.ready_timeout).await.map_err(|e| e.to_string())?;
+        self.spawn_health_check();
+        Ok(())
+    }
+
+    // Busca el contenedor compartido por nombre; si está detenido lo arranca y si no
+    // existe lo crea. Si ya hay un NodeODM respondiendo sin contenedor propio, lo usa tal cual.
+    async fn ensure_shared(&self) -> Result<(), String> {
+        let existing = self.runtime.find(&self.config.name).await.map_err(|e| e.to_string())?;
+
+        if let Some(existing) = existing {
+            let owned = !self.runtime.is_running(&existing).await.map_err(|e| e.to_string())?;
+            if owned {
+                self.runtime.start(&existing).await.map_err(|e| e.to_string())?;
+            }
+            println!("Usando el contenedor existente {} ({})", self.config.name, existing);
+            *self.shared.lock().unwrap() = SharedContainer { id: Some(existing), owned };
+            return Ok(());
+        }
+
+        if self.node().info().await.is_ok() {
+            println!("NodeODM ya responde en el puerto {}, se adopta sin contenedor.", self.config.port);
+            return Ok(());
+        }
+
+        let spec = RunSpec {
+            image: &self.config.image,
+            name: Some(&self.config.name),
+            host_port: self.config.port,
+            remove_on_stop: false,
+        };
+        let id = self.runtime.run(&spec).await.map_err(|e| e.to_string())?;
+        println!("Contenedor {} iniciado ({})", self.config.name, id);
+        *self.shared.lock().unwrap() = SharedContainer { id: Some(id), owned: true };
+        Ok(())
+    }
+
+    // Consulta `/info` periódicamente y reinicia el contenedor compartido tras
+    // varios fallos consecutivos.
+    fn spawn_health_check(&self) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let interval = Duration::from_secs(manager.config.health_check_interval_secs);
+            let mut failures = 0;
+            loop {
+                tokio::time::sleep(interval).await;
+                if manager.node().info().await.is_ok() {
+                    failures = 0;
+                    continue;
+                }
+
+                failures += 1;
+                println!("NodeODM no responde ({} fallos consecutivos)", failures);
+                if failures >= manager.config.max_health_failures {
+                    failures = 0;
+                    if let Err(err) = manager.restart_shared().await {
+                        println!("Error al reiniciar NodeODM: {}", err);
+                    }
+                }
+            }
+        });
+    }
+
+    async fn restart_shared(&self) -> Result<(), String> {
+        let id = self.shared.lock().unwrap().id.clone();
+        match id {
+            Some(id) => {
+                println!("Reiniciando el contenedor {}", id);
+                self.runtime.restart(&id).await.map_err(|e| e.to_string())?;
+            }
+            None => self.ensure_shared().await?,
+        }
+        self.node().wait_until_ready(self.ready_timeout).await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // Inicia un contenedor para un trabajo (modo por trabajo) y espera a que responda.
+    pub async fn start_job_container(&self) -> Result<NodeLease, String> {
+        let (container_id, host_port) = self.run_per_job().await?;
+        let node = NodeOdmClient::new(&self.runtime.node_url(host_port));
+        if let Err(err) = node.wait_until_ready(self.ready_timeout).await {
+            if let Err(stop_err) = self.runtime.stop(&container_id).await {
+                println!("Error al detener el contenedor {}: {}", container_id, stop_err);
+            }
+            return Err(err.to_string());
+        }
+        Ok(NodeLease { node, node_name: None, container_id: Some(container_id), host_port: Some(host_port) })
+    }
+
+    // Vuelve a usar el contenedor de un trabajo recuperado tras un reinicio.
+    pub fn attach_job_container(&self, container_id: String, host_port: u16) -> NodeLease {
+        let node = NodeOdmClient::new(&self.runtime.node_url(host_port));
+        NodeLease { node, node_name: None, container_id: Some(container_id), host_port: Some(host_port) }
+    }
+
+    // Crea un contenedor por trabajo publicado en un puerto libre del host. Entre
+    // elegir el puerto y crear el contenedor otro proceso puede tomarlo, así que se
+    // reintenta con otro puerto si el runtime lo reporta como ocupado.
+    async fn run_per_job(&self) -> Result<(String, u16), String> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let host_port = free_port().map_err(|e| format!("No se encontró un puerto libre: {}", e))?;
+            // Cada contenedor por trabajo guarda las imágenes y resultados de su tarea;
+            // se borra al detenerlo para no llenar el disco.
+            let spec = RunSpec { image: &self.config.image, name: None, host_port, remove_on_stop: true };
+            match self.runtime.run(&spec).await {
+                Ok(container_id) => return Ok((container_id, host_port)),
+                Err(RuntimeError::Failed { ref message, .. })
+                    if attempt < PORT_ATTEMPTS && is_port_conflict(message) =>
+                {
+                    println!("El puerto {} ya está en uso, se intenta con otro.", host_port);
+                }
+                Err(err) => return Err(format!("Error al iniciar el contenedor: {}", err)),
+            }
+        }
+    }
+
+    // Detiene el contenedor de un trabajo, si tiene uno propio.
+    pub async fn release(&self, lease: &NodeLease) {
+        if let Some(container_id) = &lease.container_id {
+            if let Err(err) = self.runtime.stop(container_id).await {
+                println!("Error al detener el contenedor {}: {}", container_id, err);
+            }
+        }
+    }
+
+    // Detiene un contenedor que quedó de una ejecución anterior sin trabajo activo.
+    // Si ya no existe o está detenido no hace nada.
+    pub async fn stop_orphan(&self, container_id: &str) {
+        if !self.runtime.is_running(container_id).await.unwrap_or(false) {
+            return;
+        }
+        println!("Deteniendo el contenedor huérfano {}", container_id);
+        if let Err(err) = self.runtime.stop(container_id).await {
+            println!("Error al detener el contenedor {}: {}", container_id, err);
+        }
+    }
+
+    // Detiene el contenedor compartido al apagar el servicio, solo si lo iniciamos nosotros.
+    pub async fn shutdown(&self) {
+        let shared = std::mem::take(&mut *self.shared.lock().unwrap());
+        if let (Some(id), true) = (shared.id, shared.owned) {
+            println!("Deteniendo el contenedor {}", id);
+            if let Err(err) = self.runtime.stop(&id).await {
+                println!("Error al detener el contenedor {}: {}", id, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::jobs::JobStore;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::Message;
+use serde::Deserialize;
+use std::collections::HashSet;
+use tokio::sync::broadcast::error::RecvError;
+
+// Suscripción que abarca todos los trabajos.
+const ALL_JOBS: &str = "*";
+
+// Mensajes del cliente: `{"subscribe": ["<id>", ...]}` o `{"unsubscribe": [...]}`.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    // IDs separados por comas a los que se suscribe al conectar.
+    jobs: Option<String>,
+}
+
+// GET /events — WebSocket que envía un `JobEvent` en JSON cada vez que cambia el
+// estado, el progreso o la subida de un trabajo suscrito. `"*"` suscribe a todos.
+pub async fn job_events(
+    request: HttpRequest,
+    body: web::Payload,
+    jobs: web::Data<JobStore>,
+    query: web::Query<EventsQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response, mut session, mut messages) = actix_ws::handle(&request, body)?;
+    let mut events = jobs.subscribe();
+    let mut subscribed: HashSet<String> = query
+        .jobs
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::select! {
+                message = messages.recv() => match message {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(ClientMessage::Subscribe(ids)) => subscribed.extend(ids),
+                        Ok(ClientMessage::Unsubscribe(ids)) => subscribed.retain(|id| !ids.contains(id)),
+                        Err(err) => {
+                            let error = serde_json::json!({ "error": format!("Mensaje inválido: {}", err) });
+                            if session.text(error.to_string()).await.is_err() {
+                                return;
+                            }
+                        }
+                    },
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(reason))) => {
+                        let _ = session.close(reason).await;
+                        return;
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None => return,
+                },
+                event = events.recv() => match event {
+                    Ok(event) if subscribed.contains(ALL_JOBS) || subscribed.contains(&event.job_id) => {
+                        let text = serde_json::to_string(&event).unwrap_or_default();
+                        if session.text(text).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(_) => {}
+                    // Cliente lento: se pierden los eventos más viejos pero sigue conectado.
+                    Err(RecvError::Lagged(skipped)) => {
+                        println!("Un cliente de eventos perdió {} eventos", skipped);
+                    }
+                    Err(RecvError::Closed) => {
+                        let _ = session.close(None).await;
+                        return;
+                    }
+                },
+            }
+        }
+    });
+
+    Ok(response)
+}
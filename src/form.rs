@@ -0,0 +1,133 @@
+use crate::options;
+use crate::queue::Priority;
+use actix_multipart::Field;
+use futures_util::stream::StreamExt;
+use webodm_client::nodeodm::TaskOption;
+
+// Largo máximo de un campo de texto del formulario (el GeoJSON de `boundary` es el más grande).
+const MAX_TEXT_FIELD: usize = 1024 * 1024;
+
+// Campos que acepta el formulario multipart de un trabajo.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FormField {
+    // Archivos que se envían a NodeODM.
+    Image,
+    Gcp,
+    Geo,
+    // Datos del trabajo.
+    Options,
+    Name,
+    Preset,
+    Priority,
+    Webhook,
+    Boundary,
+}
+
+const FIELDS: &[(&str, FormField)] = &[
+    ("images", FormField::Image),
+    ("gcp", FormField::Gcp),
+    ("geo", FormField::Geo),
+    ("options", FormField::Options),
+    ("name", FormField::Name),
+    ("preset", FormField::Preset),
+    ("priority", FormField::Priority),
+    ("webhook", FormField::Webhook),
+    ("boundary", FormField::Boundary),
+];
+
+impl FormField {
+    pub fn parse(name: &str) -> Result<FormField, String> {
+        FIELDS.iter().find(|(known, _)| *known == name).map(|(_, field)| *field).ok_or_else(|| {
+            let expected: Vec<&str> = FIELDS.iter().map(|(known, _)| *known).collect();
+            format!("Campo inesperado: {:?}; los campos válidos son {}", name, expected.join(", "))
+        })
+    }
+
+    // Nombre fijo con el que ODM reconoce los archivos que no son imágenes.
+    pub fn file_name(self) -> Option<&'static str> {
+        match self {
+            FormField::Gcp => Some("gcp_list.txt"),
+            FormField::Geo => Some("geo.txt"),
+            _ => None,
+        }
+    }
+
+    // Campo de un archivo ya guardado, según su nombre: los de nombre fijo son
+    // `gcp` o `geo` y el resto, imágenes.
+    pub fn for_file_name(name: &str) -> FormField {
+        FIELDS
+            .iter()
+            .map(|(_, field)| *field)
+            .find(|field| field.file_name() == Some(name))
+            .unwrap_or(FormField::Image)
+    }
+
+    pub fn is_file(self) -> bool {
+        matches!(self, FormField::Image | FormField::Gcp | FormField::Geo)
+    }
+}
+
+// Datos del trabajo recibidos en el formulario. Cada campo se acepta una sola vez.
+#[derive(Default)]
+pub struct JobForm {
+    pub name: Option<String>,
+    pub preset: Option<String>,
+    pub priority: Option<Priority>,
+    pub webhook: Option<String>,
+    pub options: Option<Vec<TaskOption>>,
+    // GeoJSON del área a procesar, que se pasa a ODM como la opción `boundary`.
+    pub boundary: Option<String>,
+}
+
+impl JobForm {
+    // Lee y valida un campo de datos.
+    pub async fn read(&mut self, kind: FormField, field: &mut Field) -> Result<(), String> {
+        let name = field.name().to_string();
+        let mut raw = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| format!("Error al leer el campo {}: {}", name, e))?;
+            if raw.len() + chunk.len() > MAX_TEXT_FIELD {
+                return Err(format!("El campo {} excede {} bytes", name, MAX_TEXT_FIELD));
+            }
+            raw.extend_from_slice(&chunk);
+        }
+        let text = || {
+            String::from_utf8(raw.clone())
+                .map(|text| text.trim().to_string())
+                .map_err(|_| format!("El campo {} no es texto UTF-8", name))
+        };
+
+        let already_set = match kind {
+            FormField::Options => self.options.replace(options::parse_options(&raw)?).is_some(),
+            FormField::Name => self.name.replace(text()?).is_some(),
+            FormField::Preset => self.preset.replace(text()?).is_some(),
+            FormField::Webhook => self.webhook.replace(text()?).is_some(),
+            FormField::Priority => {
+                let priority = serde_json::from_value(serde_json::Value::String(text()?))
+                    .map_err(|_| "El campo priority debe ser high, normal o low".to_string())?;
+                self.priority.replace(priority).is_some()
+            }
+            FormField::Boundary => {
+                let boundary = text()?;
+                serde_json::from_str::<serde_json::Value>(&boundary)
+                    .map_err(|e| format!("El campo boundary no es GeoJSON válido: {}", e))?;
+                self.boundary.replace(boundary).is_some()
+            }
+            FormField::Image | FormField::Gcp | FormField::Geo => false,
+        };
+        if already_set {
+            return Err(format!("El campo {} se envió más de una vez", name));
+        }
+        Ok(())
+    }
+
+    // Opciones de ODM de la petición, con `boundary` si se envió.
+    pub fn task_options(&mut self) -> Vec<TaskOption> {
+        let mut task_options = self.options.take().unwrap_or_default();
+        if let Some(boundary) = self.boundary.take() {
+            task_options.retain(|option| option.name != "boundary");
+            task_options.push(TaskOption { name: "boundary".to_string(), value: serde_json::Value::String(boundary) });
+        }
+        task_options
+    }
+}
@@ -0,0 +1,132 @@
+use actix_web::mime::Mime;
+use serde::{Deserialize, Serialize};
+
+// Largo máximo del nombre sin la extensión.
+const MAX_STEM_LEN: usize = 100;
+// Una "extensión" más larga no lo es: se toma como parte del nombre.
+const MAX_EXTENSION_LEN: usize = 10;
+
+// Tipos de archivo que acepta NodeODM y su extensión. La primera extensión de
+// cada tipo es la que se usa cuando el nombre no trae una.
+const TYPES: &[(&str, &[&str])] = &[
+    ("image/jpeg", &["jpg", "jpeg", "jpe"]),
+    ("image/png", &["png"]),
+    ("image/tiff", &["tif", "tiff"]),
+    ("image/x-adobe-dng", &["dng"]),
+    ("text/plain", &["txt"]),
+];
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Imagen recibida para un trabajo: el nombre con el que se guardó (y con el que
+// la ve NodeODM) y su tipo de contenido.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JobImage {
+    pub name: String,
+    pub content_type: String,
+}
+
+impl JobImage {
+    // Imagen ya guardada sin tipo registrado (trabajos anteriores a este dato).
+    pub fn from_name(name: String) -> Self {
+        let content_type = content_type_for(&name).unwrap_or(DEFAULT_CONTENT_TYPE).to_string();
+        JobImage { name, content_type }
+    }
+}
+
+// Tipo de contenido según la extensión del nombre.
+fn content_type_for(name: &str) -> Option<&'static str> {
+    let (_, extension) = name.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    TYPES
+        .iter()
+        .find(|(_, extensions)| extensions.contains(&extension.as_str()))
+        .map(|(content_type, _)| *content_type)
+}
+
+fn extension_for(content_type: &str) -> Option<&'static str> {
+    TYPES.iter().find(|(known, _)| *known == content_type).map(|(_, extensions)| extensions[0])
+}
+
+// Tipo de contenido declarado por el cliente si es válido y específico; si no,
+// el que corresponde a la extensión.
+pub fn resolve_content_type(declared: Option<&str>, name: &str) -> String {
+    declared
+        .and_then(|value| value.parse::<Mime>().ok())
+        .map(|mime| mime.essence_str().to_string())
+        .filter(|essence| essence != DEFAULT_CONTENT_TYPE)
+        .or_else(|| content_type_for(name).map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string())
+}
+
+// Nombre seguro a partir del que mandó el cliente: sin directorios, solo letras
+// y números ASCII, `-`, `_` y `.`, y siempre con extensión (la del tipo de
+// contenido o `.jpg`), porque NodeODM elige los archivos por extensión.
+// Devuelve el nombre sin extensión y la extensión por separado.
+pub fn sanitize_filename(filename: Option<&str>, content_type: Option<&str>) -> (String, String) {
+    // Algunos navegadores mandan la ruta completa del archivo.
+    let base = filename.unwrap_or_default().rsplit(['/', '\\']).next().unwrap_or_default();
+    let clean: String = base
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    let clean = clean.trim_start_matches('.');
+
+    let (stem, extension) = match clean.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.is_empty() && extension.len() <= MAX_EXTENSION_LEN => {
+            (stem, extension.to_string())
+        }
+        _ => {
+            let extension = content_type.and_then(extension_for).unwrap_or("jpg");
+            (clean.trim_end_matches('.'), extension.to_string())
+        }
+    };
+    let stem: String = stem.chars().take(MAX_STEM_LEN).collect();
+    let stem = if stem.is_empty() { "image".to_string() } else { stem };
+    (stem, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitize(filename: &str, content_type: Option<&str>) -> (String, String) {
+        sanitize_filename(Some(filename), content_type)
+    }
+
+    #[test]
+    fn sanitize_strips_directories() {
+        assert_eq!(sanitize("../x", None), ("x".to_string(), "jpg".to_string()));
+        assert_eq!(sanitize("C:\\a\\b.JPG", None), ("b".to_string(), "JPG".to_string()));
+        assert_eq!(sanitize("/fotos/vuelo 1/DJI 0001.JPG", None), ("DJI_0001".to_string(), "JPG".to_string()));
+    }
+
+    #[test]
+    fn sanitize_never_returns_dots_or_empty_names() {
+        assert_eq!(sanitize("..", None), ("image".to_string(), "jpg".to_string()));
+        assert_eq!(sanitize(".hidden.png", None), ("hidden".to_string(), "png".to_string()));
+        assert_eq!(sanitize_filename(None, None), ("image".to_string(), "jpg".to_string()));
+        assert_eq!(sanitize("ñandú.tif", None), ("_and_".to_string(), "tif".to_string()));
+    }
+
+    #[test]
+    fn sanitize_takes_extension_from_content_type() {
+        assert_eq!(sanitize("foto", Some("image/png")), ("foto".to_string(), "png".to_string()));
+        assert_eq!(sanitize("foto.", Some("image/tiff")), ("foto".to_string(), "tif".to_string()));
+        assert_eq!(sanitize("foto", Some("application/octet-stream")), ("foto".to_string(), "jpg".to_string()));
+        // La extensión del nombre tiene prioridad sobre el tipo declarado.
+        assert_eq!(sanitize("foto.jpg", Some("image/png")), ("foto".to_string(), "jpg".to_string()));
+    }
+
+    #[test]
+    fn sanitize_limits_long_names() {
+        let (stem, extension) = sanitize(&format!("{}.jpg", "a".repeat(300)), None);
+        assert_eq!(stem, "a".repeat(MAX_STEM_LEN));
+        assert_eq!(extension, "jpg");
+
+        let (stem, extension) = sanitize(&format!("foto.{}", "b".repeat(300)), Some("image/png"));
+        assert_eq!(stem.len(), MAX_STEM_LEN);
+        assert!(stem.starts_with("foto.b"));
+        assert_eq!(extension, "png");
+    }
+}
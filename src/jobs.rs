@@ -0,0 +1,470 @@
+use crate::images::JobImage;
+use crate::queue::Priority;
+use crate::webhooks::WebhookDelivery;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::mem;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+use webodm_client::nodeodm::TaskOption;
+
+// Estados posibles de un trabajo de reconstrucción.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    // Creado por partes: espera sus imágenes. Sin `expected`, hasta que se confirme
+    // con `/jobs/{id}/commit`.
+    Receiving { received: usize, expected: Option<usize> },
+    Queued,
+    StartingContainer,
+    Uploading { uploaded: usize, total: usize },
+    Committed,
+    Running { progress: f64 },
+    Downloading,
+    Completed,
+    Failed,
+    Canceled,
+    // Se perdió el nodo con la tarea ya enviada; las imágenes se conservan para reenviarla.
+    NodeLost,
+}
+
+impl JobState {
+    // Nombre del estado tal como aparece en la API (`"state"`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobState::Receiving { .. } => "receiving",
+            JobState::Queued => "queued",
+            JobState::StartingContainer => "starting_container",
+            JobState::Uploading { .. } => "uploading",
+            JobState::Committed => "committed",
+            JobState::Running { .. } => "running",
+            JobState::Downloading => "downloading",
+            JobState::Completed => "completed",
+            JobState::Failed => "failed",
+            JobState::Canceled => "canceled",
+            JobState::NodeLost => "node_lost",
+        }
+    }
+
+    // Estados en los que el trabajo ya no avanza por sí solo.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, JobState::Completed | JobState::Failed | JobState::Canceled | JobState::NodeLost)
+    }
+
+    // La tarea ya existe en NodeODM con todas sus imágenes.
+    pub fn is_committed(&self) -> bool {
+        matches!(self, JobState::Committed | JobState::Running { .. } | JobState::Downloading)
+    }
+}
+
+// Registro de un cambio de estado con su marca de tiempo.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StateChange {
+    #[serde(flatten)]
+    pub state: JobState,
+    pub at: DateTime<Utc>,
+}
+
+// Un trabajo de reconstrucción que se ejecuta en segundo plano.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Job {
+    pub id: String,
+    // Nombre descriptivo que da el cliente.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub state: JobState,
+    // Imágenes recibidas (campos `images` o subidas tus), sin contar `gcp` ni `geo`.
+    pub image_count: usize,
+    // Imágenes recibidas con su nombre y tipo de contenido.
+    #[serde(default)]
+    pub images: Vec<JobImage>,
+    pub preset: Option<String>,
+    #[serde(default)]
+    pub priority: Priority,
+    // Opciones de ODM ya resueltas (preset + opciones de la petición) con las que se procesa.
+    pub options: Vec<TaskOption>,
+    // Nodo registrado que procesa el trabajo.
+    pub node: Option<String>,
+    // UUID de la tarea en NodeODM, para volver a consultarla tras un reinicio.
+    pub task_uuid: Option<String>,
+    // Contenedor de NodeODM y su puerto en el host cuando se usa uno por trabajo.
+    pub container_id: Option<String>,
+    pub host_port: Option<u16>,
+    pub error: Option<String>,
+    // Últimas líneas de la consola de ODM cuando la tarea falla.
+    pub output_tail: Vec<String>,
+    // Archivos de resultado descargados.
+    pub artifacts: Vec<String>,
+    // URL que recibe el aviso cuando el trabajo termina.
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    #[serde(default)]
+    pub deliveries: Vec<WebhookDelivery>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub history: Vec<StateChange>,
+}
+
+// Datos con los que se crea un trabajo.
+pub struct NewJob {
+    // `Queued` si ya tiene sus imágenes o `Receiving` si llegan después.
+    pub state: JobState,
+    pub name: Option<String>,
+    pub image_count: usize,
+    pub preset: Option<String>,
+    pub priority: Priority,
+    pub options: Vec<TaskOption>,
+    pub callback_url: Option<String>,
+}
+
+// Cambio de estado o de avance de un trabajo, enviado a los suscriptores de eventos.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobEvent {
+    pub job_id: String,
+    pub old_state: &'static str,
+    pub new_state: &'static str,
+    // Porcentaje de avance de ODM mientras la tarea corre.
+    pub progress: Option<f64>,
+    // Imágenes subidas y total durante la subida.
+    pub uploaded: Option<usize>,
+    pub total: Option<usize>,
+    pub at: DateTime<Utc>,
+}
+
+impl JobEvent {
+    fn new(job_id: &str, old: &JobState, new: &JobState, at: DateTime<Utc>) -> Self {
+        let (progress, uploaded, total) = match new {
+            JobState::Running { progress } => (Some(*progress), None, None),
+            JobState::Uploading { uploaded, total } => (None, Some(*uploaded), Some(*total)),
+            JobState::Receiving { received, expected } => (None, Some(*received), *expected),
+            JobState::Completed => (Some(100.0), None, None),
+            _ => (None, None, None),
+        };
+        JobEvent { job_id: job_id.to_string(), old_state: old.name(), new_state: new.name(), progress, uploaded, total, at }
+    }
+}
+
+// Eventos que se guardan para suscriptores lentos antes de descartar los más viejos.
+const EVENT_BUFFER: usize = 256;
+
+// Esquema de la base de datos de trabajos. El trabajo completo se guarda como
+// JSON en `data`; el resto de columnas permiten consultarlo sin deserializarlo.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS jobs (
+        id TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        node TEXT,
+        task_uuid TEXT,
+        container_id TEXT,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+";
+
+// Registro de los trabajos, compartido entre los handlers y las tareas de fondo.
+// Se mantiene en memoria y se escribe en SQLite en cada cambio para sobrevivir a
+// reinicios del servicio.
+#[derive(Clone)]
+pub struct JobStore {
+    jobs: Arc<Mutex<HashMap<String, Job>>>,
+    db: Arc<Mutex<Connection>>,
+    events: broadcast::Sender<JobEvent>,
+}
+
+impl JobStore {
+    // Abre (o crea) la base de datos y carga los trabajos guardados.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let db = Connection::open(path)?;
+        db.execute_batch(SCHEMA)?;
+
+        let mut jobs = HashMap::new();
+        {
+            let mut statement = db.prepare("SELECT id, data FROM jobs")?;
+            let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+            for row in rows {
+                let (id, data) = row?;
+                match serde_json::from_str::<Job>(&data) {
+                    Ok(job) => {
+                        jobs.insert(job.id.clone(), job);
+                    }
+                    Err(err) => println!("No se pudo leer el trabajo guardado {}: {}", id, err),
+                }
+            }
+        }
+
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        Ok(JobStore { jobs: Arc::new(Mutex::new(jobs)), db: Arc::new(Mutex::new(db)), events })
+    }
+
+    // Recibe los eventos de todos los trabajos a partir de ahora.
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.events.subscribe()
+    }
+
+    // Escribe el trabajo en la base de datos. Un error de escritura no detiene el
+    // trabajo; solo se registra.
+    fn persist(&self, job: &Job) {
+        let result = serde_json::to_string(job).map_err(|e| e.to_string()).and_then(|data| {
+            self.db
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT INTO jobs (id, state, node, task_uuid, container_id, created_at, updated_at, data)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                     ON CONFLICT(id) DO UPDATE SET
+                         state = excluded.state, node = excluded.node, task_uuid = excluded.task_uuid,
+                         container_id = excluded.container_id, updated_at = excluded.updated_at, data = excluded.data",
+                    params![
+                        job.id,
+                        job.state.name(),
+                        job.node,
+                        job.task_uuid,
+                        job.container_id,
+                        job.created_at.to_rfc3339(),
+                        job.updated_at.to_rfc3339(),
+                        data,
+                    ],
+                )
+                .map_err(|e| e.to_string())
+        });
+        if let Err(err) = result {
+            println!("Error al guardar el trabajo {}: {}", job.id, err);
+        }
+    }
+
+    // Aplica `change` al trabajo y lo guarda. El candado se mantiene durante la
+    // escritura para que la base de datos reciba los cambios en orden.
+    fn update<R>(&self, id: &str, change: impl FnOnce(&mut Job) -> R) -> Option<R> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(id)?;
+        let result = change(job);
+        self.persist(job);
+        Some(result)
+    }
+
+    // Crea un trabajo nuevo y devuelve una copia.
+    pub fn create(&self, new: NewJob) -> Job {
+        let now = Utc::now();
+        let job = Job {
+            id: Uuid::new_v4().to_string(),
+            name: new.name,
+            state: new.state.clone(),
+            image_count: new.image_count,
+            images: Vec::new(),
+            preset: new.preset,
+            priority: new.priority,
+            options: new.options,
+            node: None,
+            task_uuid: None,
+            container_id: None,
+            host_port: None,
+            error: None,
+            output_tail: Vec::new(),
+            artifacts: Vec::new(),
+            callback_url: new.callback_url,
+            deliveries: Vec::new(),
+            created_at: now,
+            updated_at: now,
+            history: vec![StateChange { state: new.state, at: now }],
+        };
+        let mut jobs = self.jobs.lock().unwrap();
+        self.persist(&job);
+        jobs.insert(job.id.clone(), job.clone());
+        job
+    }
+
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    // Actualiza el estado del trabajo. Solo se agrega una entrada al historial cuando
+    // cambia el tipo de estado; el avance dentro del mismo estado (imágenes subidas,
+    // porcentaje de progreso) se actualiza en la última entrada. Cada cambio se
+    // publica como `JobEvent`.
+    pub fn set_state(&self, id: &str, state: JobState) {
+        self.update(id, |job| self.apply_state(job, state));
+    }
+
+    fn apply_state(&self, job: &mut Job, state: JobState) {
+        let now = Utc::now();
+        if job.state != state {
+            // Sin suscriptores `send` falla; no es un error.
+            let _ = self.events.send(JobEvent::new(&job.id, &job.state, &state, now));
+        }
+        match job.history.last_mut() {
+            Some(last) if mem::discriminant(&last.state) == mem::discriminant(&state) => {
+                last.state = state.clone();
+            }
+            _ => job.history.push(StateChange { state: state.clone(), at: now }),
+        }
+        job.state = state;
+        job.updated_at = now;
+    }
+
+    // Cuenta una imagen recibida de un trabajo en `Receiving`. Cuando llegan todas
+    // las esperadas el trabajo pasa a `Queued` y devuelve `Some(true)`; `None` si
+    // el trabajo ya no espera imágenes.
+    pub fn receive_image(&self, id: &str, image: JobImage) -> Option<bool> {
+        self.update(id, |job| match job.state {
+            JobState::Receiving { received, expected: Some(expected) } if received + 1 >= expected => {
+                job.images.push(image);
+                job.image_count = received + 1;
+                self.apply_state(job, JobState::Queued);
+                Some(true)
+            }
+            JobState::Receiving { received, expected } => {
+                job.images.push(image);
+                job.image_count = received + 1;
+                self.apply_state(job, JobState::Receiving { received: received + 1, expected });
+                Some(false)
+            }
+            _ => None,
+        })
+        .flatten()
+    }
+
+    // Registra un archivo de datos (`gcp`, `geo`) de un trabajo en `Receiving`. No
+    // cuenta como imagen; `false` si el trabajo ya no espera archivos.
+    pub fn receive_file(&self, id: &str, file: JobImage) -> bool {
+        self.update(id, |job| match job.state {
+            JobState::Receiving { .. } => {
+                job.images.push(file);
+                true
+            }
+            _ => false,
+        })
+        .unwrap_or(false)
+    }
+
+    // Quita un archivo de un trabajo en `Receiving` y, si era una imagen
+    // (`counted`), la descuenta. La verificación y el cambio se hacen juntos, así
+    // que no se quita nada de un trabajo ya confirmado. Devuelve `Some(false)` si
+    // el trabajo no tiene ese archivo y `None` si ya no espera imágenes.
+    pub fn unreceive_image(&self, id: &str, name: &str, counted: bool) -> Option<bool> {
+        self.update(id, |job| match job.state {
+            JobState::Receiving { received, expected } => {
+                let before = job.images.len();
+                job.images.retain(|image| image.name != name);
+                if job.images.len() == before {
+                    return Some(false);
+                }
+                if counted {
+                    job.image_count = received.saturating_sub(1);
+                    self.apply_state(job, JobState::Receiving { received: received.saturating_sub(1), expected });
+                }
+                Some(true)
+            }
+            _ => None,
+        })
+        .flatten()
+    }
+
+    // Registra una imagen de un trabajo creado con todas sus imágenes.
+    pub fn add_image(&self, id: &str, image: JobImage) {
+        self.update(id, |job| job.images.push(image));
+    }
+
+    // Confirma un trabajo en `Receiving` con las imágenes que tiene y lo pasa a
+    // `Queued`.
+    pub fn commit(&self, id: &str) -> Result<(), String> {
+        self.update(id, |job| match job.state {
+            JobState::Receiving { received: 0, .. } => Err("El trabajo no tiene imágenes".to_string()),
+            JobState::Receiving { received, expected: Some(expected) } if received < expected => {
+                Err(format!("Faltan {} de {} imágenes", expected - received, expected))
+            }
+            JobState::Receiving { received, .. } => {
+                job.image_count = received;
+                self.apply_state(job, JobState::Queued);
+                Ok(())
+            }
+            _ => Err("El trabajo ya no recibe imágenes".to_string()),
+        })
+        .unwrap_or_else(|| Err("Trabajo no encontrado".to_string()))
+    }
+
+    // Vuelve a encolar un trabajo en `NodeLost`. La verificación y el cambio se
+    // hacen juntos, así que dos peticiones a la vez no lanzan dos reconstrucciones.
+    pub fn resubmit(&self, id: &str) -> Result<(), String> {
+        self.update(id, |job| match job.state {
+            JobState::NodeLost => {
+                self.apply_state(job, JobState::Queued);
+                Ok(())
+            }
+            _ => Err("Solo se pueden reenviar trabajos cuyo nodo se perdió".to_string()),
+        })
+        .unwrap_or_else(|| Err("Trabajo no encontrado".to_string()))
+    }
+
+    // Registra el nodo asignado y, en modo por trabajo, su contenedor. Una
+    // asignación nueva todavía no tiene tarea en NodeODM.
+    pub fn set_node(&self, id: &str, node: Option<String>, container_id: Option<String>, host_port: Option<u16>) {
+        self.update(id, |job| {
+            job.task_uuid = None;
+            job.node = node;
+            job.container_id = container_id;
+            job.host_port = host_port;
+        });
+    }
+
+    // Prepara un trabajo fallido o cancelado para volver a procesarlo con `options`.
+    // La verificación y el cambio se hacen juntos, así que dos peticiones a la vez
+    // no lanzan dos reconstrucciones.
+    pub fn restart(&self, id: &str, options: Vec<TaskOption>) -> Result<(), String> {
+        self.update(id, |job| match job.state {
+            JobState::Failed | JobState::Canceled => {
+                job.options = options;
+                job.error = None;
+                job.output_tail.clear();
+                self.apply_state(job, JobState::Queued);
+                Ok(())
+            }
+            _ => Err("Solo se pueden reiniciar trabajos fallidos o cancelados".to_string()),
+        })
+        .unwrap_or_else(|| Err("Trabajo no encontrado".to_string()))
+    }
+
+    // Quita el trabajo del registro y de la base de datos.
+    pub fn remove(&self, id: &str) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Err(err) = self.db.lock().unwrap().execute("DELETE FROM jobs WHERE id = ?1", params![id]) {
+            println!("Error al eliminar el trabajo {}: {}", id, err);
+        }
+        jobs.remove(id);
+    }
+
+    pub fn set_task_uuid(&self, id: &str, task_uuid: Option<String>) {
+        self.update(id, |job| job.task_uuid = task_uuid);
+    }
+
+    pub fn add_artifact(&self, id: &str, path: String) {
+        self.update(id, |job| job.artifacts.push(path));
+    }
+
+    pub fn add_delivery(&self, id: &str, delivery: WebhookDelivery) {
+        self.update(id, |job| job.deliveries.push(delivery));
+    }
+
+    pub fn set_output_tail(&self, id: &str, lines: Vec<String>) {
+        self.update(id, |job| job.output_tail = lines);
+    }
+
+    pub fn set_error(&self, id: &str, error: String) {
+        self.update(id, |job| job.error = Some(error));
+    }
+
+    // Marca el trabajo como fallido guardando el mensaje de error.
+    pub fn fail(&self, id: &str, error: String) {
+        self.set_error(id, error);
+        self.set_state(id, JobState::Failed);
+    }
+}
@@ -1,172 +1,609 @@
+mod config;
+mod console;
+mod container;
+mod events;
+mod form;
+mod images;
+mod jobs;
+mod nodes;
+mod options;
+mod pipeline;
+mod presets;
+mod queue;
+mod runtime;
+mod settings;
+mod tus;
+mod webhooks;
+
 use actix_web::{web, App, HttpServer, HttpResponse,  Error};
+use actix_web::http::Method;
 use actix_cors::Cors;
-use actix_multipart::Multipart;
+use actix_multipart::{Field, Multipart};
+use form::{FormField, JobForm};
 use futures_util::stream::StreamExt;
-use reqwest::multipart::{Form, Part};
-//use serde::{Deserialize, Serialize};
+use jobs::{JobState, JobStore, NewJob};
+use nodes::NodeRegistry;
+use config::Config;
+use container::{ContainerManager, ContainerMode};
+use options::OptionSchemaCache;
+use pipeline::{Cancellations, Pipeline};
+use presets::PresetStore;
+use queue::{JobQueue, Priority};
+use serde::Deserialize;
 use std::fs;
-use std::io::{self, Read, Write};
-use std::process::Command;
-use std::thread;
+use std::path::PathBuf;
 use std::time::Duration;
-use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use settings::SettingsStore;
+use tus::TusUploads;
+use webhooks::Webhooks;
+use webodm_client::nodeodm::TaskOption;
+
+#[derive(Deserialize)]
+struct ReconstructionQuery {
+    name: Option<String>,
+    preset: Option<String>,
+    #[serde(default)]
+    priority: Priority,
+    callback_url: Option<String>,
+}
 
-// Esta función inicia el contenedor y devuelve su ID.
-fn start_container() -> Result<String, io::Error> {
-    let output = Command::new("docker")
-        .arg("run")
-        .arg("-d")
-        .arg("-p")
-        .arg("3000:3000")
-        .arg("opendronemap/nodeodm")
-        .output()?;
-    
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+// Respuesta 429 si la cola no acepta más trabajos.
+fn queue_full(pipeline: &Pipeline) -> Option<HttpResponse> {
+    if !pipeline.queue.is_full() {
+        return None;
+    }
+    let retry_after = pipeline.queue.retry_after().as_secs().max(1);
+    Some(
+        HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after.to_string()))
+            .body("La cola de trabajos está llena"),
+    )
 }
 
-// Esta función detiene el contenedor dado un ID.
-fn stop_container(container_id: &str) -> Result<(), io::Error> {
-    let output = Command::new("docker")
-        .arg("stop")
-        .arg(container_id)
-        .output()?;
-    println!("Docker stop output: {:?}", output);
+// Resuelve el preset y valida las opciones y la URL de callback de un trabajo nuevo.
+async fn prepare_job(
+    pipeline: &Pipeline,
+    presets: &PresetStore,
+    query: ReconstructionQuery,
+    overrides: Vec<TaskOption>,
+    state: JobState,
+    image_count: usize,
+) -> Result<NewJob, String> {
+    let ReconstructionQuery { name, preset, priority, callback_url } = query;
+    if let Some(url) = &callback_url {
+        reqwest::Url::parse(url).map_err(|e| format!("callback_url no es válida: {}", e))?;
+    }
+    let task_options = presets.resolve(preset.as_deref(), overrides)?;
+
+    // Validar contra el esquema de los nodos; si ninguno responde, se valida en
+    // segundo plano antes de crear la tarea.
+    if let Some(schema) = pipeline.schema.load(&pipeline.nodes).await {
+        options::validate_options(&schema, &task_options)?;
+    }
+    Ok(NewJob { state, name, image_count, preset, priority, options: task_options, callback_url })
+}
+
+// Nombre de archivo y tipo de contenido que el cliente declaró para un campo.
+fn field_file(field: &Field) -> (Option<String>, String) {
+    let filename = field.content_disposition().get_filename().map(str::to_string);
+    (filename, field.content_type().to_string())
+}
+
+// Escribe un campo de archivo en disco. Cada bloque se escribe al llegar, así que
+// la memoria no crece con el tamaño de la imagen.
+async fn stage_field(field: &mut Field, path: &std::path::Path) -> Result<(), Error> {
+    let mut file = tokio::fs::File::create(path).await?;
+    while let Some(chunk) = field.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await?;
     Ok(())
 }
 
-// Endpoint para iniciar todo el proceso de reconstrucción
-async fn start_reconstruction(mut payload: Multipart) -> Result<HttpResponse, Error> {
-    match start_container() {
-        Ok(container_id) => {
-            let client = reqwest::Client::new();
-            
-            thread::sleep(Duration::from_secs(5));
+// Endpoint para iniciar el proceso de reconstrucción. Guarda las imágenes en disco,
+// encola el trabajo y responde de inmediato con su ID; el resto corre en segundo plano.
+// Los campos `images` son las imágenes y `gcp` y `geo` los archivos de puntos de
+// control y de geolocalización; `options`, `name`, `preset`, `priority`, `webhook`
+// y `boundary` son datos del trabajo. Cualquier otro campo se rechaza con 400.
+// `?preset=<nombre>` parte de un preset; el campo `options` sobrescribe sus valores.
+// `?priority=high|normal|low` ordena el trabajo en la cola; si está llena responde 429.
+// `?callback_url=<url>` recibe un webhook cuando el trabajo termina.
+// Los campos del formulario tienen prioridad sobre los parámetros de la URL.
+async fn start_reconstruction(
+    pipeline: web::Data<Pipeline>,
+    presets: web::Data<PresetStore>,
+    query: web::Query<ReconstructionQuery>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = queue_full(&pipeline) {
+        return Ok(response);
+    }
+    fs::create_dir_all(pipeline.uploads_dir())?;
+    let staging = tempfile::tempdir_in(pipeline.uploads_dir())?;
+    let mut files = Vec::new();
+    let mut image_count = 0;
+    let mut form = JobForm::default();
+
+    // Iterate over each field in the multipart form data
+    while let Some(field) = payload.next().await {
+        // Un formulario mal formado se rechaza entero; los archivos ya guardados
+        // se borran con `staging`.
+        let mut field = match field {
+            Ok(field) => field,
+            Err(err) => return Ok(HttpResponse::BadRequest().body(format!("Formulario inválido: {}", err))),
+        };
+        let kind = match FormField::parse(field.name()) {
+            Ok(kind) => kind,
+            Err(err) => return Ok(HttpResponse::BadRequest().body(err)),
+        };
+        if !kind.is_file() {
+            if let Err(err) = form.read(kind, &mut field).await {
+                return Ok(HttpResponse::BadRequest().body(err));
+            }
+            continue;
+        }
+        if kind.file_name().is_some() && files.iter().any(|(_, other, _, _)| *other == kind) {
+            return Ok(HttpResponse::BadRequest().body(format!("El campo {} se envió más de una vez", field.name())));
+        }
 
-            // 1. Initialize a new task
-            let init_url = "http://localhost:3000/task/new/init";
-            let resp_init = client.post(init_url).send().await.unwrap();
-            let data: serde_json::Value = resp_init.json().await.unwrap();
-            let token = data["uuid"].as_str().expect("Token not found").to_string();
+        let path = staging.path().join(format!("field_{}", files.len() + 1));
+        let (filename, content_type) = field_file(&field);
+        stage_field(&mut field, &path).await?;
+        if kind == FormField::Image {
+            image_count += 1;
+        }
+        files.push((path, kind, filename, content_type));
+    }
+    if image_count == 0 {
+        return Ok(HttpResponse::BadRequest().body("El formulario no tiene imágenes (campo images)"));
+    }
 
-            let mut image_count = 0;
+    let query = query.into_inner();
+    let settings = ReconstructionQuery {
+        name: form.name.take().or(query.name),
+        preset: form.preset.take().or(query.preset),
+        priority: form.priority.unwrap_or(query.priority),
+        callback_url: form.webhook.take().or(query.callback_url),
+    };
+    let task_options = form.task_options();
+    let new_job = prepare_job(&pipeline, &presets, settings, task_options, JobState::Queued, image_count).await;
+    let job = match new_job {
+        Ok(new_job) => pipeline.jobs.create(new_job),
+        Err(err) => return Ok(HttpResponse::BadRequest().body(err)),
+    };
+    for (path, kind, filename, content_type) in &files {
+        let filename = kind.file_name().or(filename.as_deref());
+        match pipeline.add_image(&job.id, path, filename, Some(content_type)) {
+            Ok(image) => pipeline.jobs.add_image(&job.id, image),
+            // Sin todas sus imágenes el trabajo no debe procesarse (ni al recuperarlo).
+            Err(err) => {
+                let err = format!("Error al guardar las imágenes del trabajo: {}", err);
+                pipeline.jobs.fail(&job.id, err.clone());
+                return Ok(HttpResponse::InternalServerError().body(err));
+            }
+        }
+    }
+    tokio::spawn(pipeline.get_ref().clone().run(job.id.clone()));
 
-            // Iterate over each field in the multipart form data
-            while let Some(Ok(mut field)) = payload.next().await {
-                let mut data = Vec::new();
-                while let Some(chunk) = field.next().await {
-                    let chunk = chunk.unwrap();
-                    data.extend_from_slice(&chunk);
-                }
-                image_count += 1;
+    Ok(HttpResponse::Accepted().json(serde_json::json!({ "job_id": job.id })))
+}
 
-                // Create a temporary file to store the image
-                let mut file = NamedTempFile::new().unwrap();
-                file.write_all(&data).unwrap();
-                let file_path = file.into_temp_path();
-        
-                // Read the file content into a vector of bytes
-                let mut file_content = Vec::new();
-                fs::File::open(&file_path)
-                    .expect("Failed to open file")
-                    .read_to_end(&mut file_content)
-                    .expect("Failed to read file");
+#[derive(Deserialize)]
+struct CreateJobBody {
+    // Número de imágenes que se subirán con `/uploads`; sin él, el trabajo espera
+    // hasta `/jobs/{id}/commit`.
+    images: Option<usize>,
+    #[serde(default)]
+    options: Vec<TaskOption>,
+    #[serde(flatten)]
+    settings: ReconstructionQuery,
+}
 
-                // Upload the image to the server
-                let upload_url = format!("http://localhost:3000/task/new/upload/{}?token={}", token, token);
-                let part = Part::bytes(file_content).file_name("image.jpg".to_owned());
-                let form = Form::new().part("images", part);
-                let resp_upload = client.post(&upload_url).multipart(form).send().await.unwrap();
-                println!("Uploaded image {} - Response: {:?}", image_count, resp_upload);
-            }
+// Endpoint para crear un trabajo cuyas imágenes se suben después, con tus
+// (`/uploads`) o por tandas (`/jobs/{id}/images`). El trabajo espera en `receiving`
+// y se encola al llegar la última imagen indicada en `images` o con `/jobs/{id}/commit`.
+async fn create_job(
+    pipeline: web::Data<Pipeline>,
+    presets: web::Data<PresetStore>,
+    body: web::Json<CreateJobBody>,
+) -> HttpResponse {
+    if let Some(response) = queue_full(&pipeline) {
+        return response;
+    }
+    let CreateJobBody { images, options, settings } = body.into_inner();
+    if images == Some(0) {
+        return HttpResponse::BadRequest().body("El trabajo necesita al menos una imagen");
+    }
+    let state = JobState::Receiving { received: 0, expected: images };
+    // `image_count` cuenta las imágenes recibidas, como en los demás caminos.
+    match prepare_job(&pipeline, &presets, settings, options, state, 0).await {
+        Ok(new_job) => {
+            let job = pipeline.jobs.create(new_job);
+            HttpResponse::Created().json(job)
+        }
+        Err(err) => HttpResponse::BadRequest().body(err),
+    }
+}
 
-            // 3. Commit the task
-            let commit_url = format!("http://localhost:3000/task/new/commit/{}", token);
-            let resp_commit = client.post(&commit_url).send().await.unwrap();
-            println!("Task commit response: {:?}", resp_commit);
+// Respuesta de error si el trabajo no existe o ya no recibe imágenes.
+fn check_receiving(pipeline: &Pipeline, id: &str) -> Option<HttpResponse> {
+    match pipeline.jobs.get(id) {
+        Some(job) if matches!(job.state, JobState::Receiving { .. }) => None,
+        Some(_) => Some(HttpResponse::Conflict().body("El trabajo ya no recibe imágenes")),
+        None => Some(HttpResponse::NotFound().body("Trabajo no encontrado")),
+    }
+}
 
-            // 4. Verificar si la tarea ha terminado.
-            let mut task_complete = false;
+// Endpoint para agregar imágenes a un trabajo en `receiving`. Acepta los campos de
+// archivo de `/start_reconstruction` (`images`, `gcp` y `geo`); solo `images` cuenta
+// para las imágenes esperadas. Se puede llamar varias veces, también en paralelo;
+// responde con los nombres asignados, que sirven para quitarlas con
+// `DELETE /jobs/{id}/images/{name}`. Los archivos se agregan al trabajo recién
+// cuando se leyó todo el formulario, así que uno mal formado no agrega ninguno.
+async fn add_images(
+    pipeline: web::Data<Pipeline>,
+    path: web::Path<String>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let id = path.into_inner();
+    if let Some(response) = check_receiving(&pipeline, &id) {
+        return Ok(response);
+    }
+    fs::create_dir_all(pipeline.uploads_dir())?;
+    let staging = tempfile::tempdir_in(pipeline.uploads_dir())?;
+    let mut files = Vec::new();
+
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(err) => return Ok(HttpResponse::BadRequest().body(format!("Formulario inválido: {}", err))),
+        };
+        let kind = match FormField::parse(field.name()) {
+            Ok(kind) if kind.is_file() => kind,
+            Ok(_) => {
+                let err = format!("El campo {} solo se acepta al crear el trabajo", field.name());
+                return Ok(HttpResponse::BadRequest().body(err));
+            }
+            Err(err) => return Ok(HttpResponse::BadRequest().body(err)),
+        };
+        let (filename, content_type) = field_file(&field);
+        let filename = kind.file_name().map(str::to_string).or(filename);
+        if let Some(name) = kind.file_name() {
+            if pipeline.images_dir(&id).join(name).exists() || files.iter().any(|(_, other, _, _)| *other == kind) {
+                return Ok(HttpResponse::BadRequest().body(format!("El campo {} ya se envió para este trabajo", field.name())));
+            }
+        }
+        let path = staging.path().join(format!("field_{}", files.len() + 1));
+        stage_field(&mut field, &path).await?;
+        files.push((path, kind, filename, content_type));
+    }
 
-            while !task_complete {
-                thread::sleep(Duration::from_secs(10));  // Espera antes de verificar nuevamente.
-                let info_url = format!("http://localhost:3000/task/{}/info", token);
-                let resp_info = match client.get(&info_url).send().await {
-                    Ok(resp) => resp,
-                    Err(_err) => {
-                        // Manejar el error de conexión cerrada antes de completar el mensaje
-                        //println!("Error al enviar la solicitud de información de la tarea: {}", err);
-                        continue; // Volver al principio del bucle para intentarlo nuevamente
-                    }
-                };
+    // Un lote con más imágenes de las que faltan se rechaza sin agregar ninguna.
+    let images = files.iter().filter(|(_, kind, _, _)| *kind == FormField::Image).count();
+    if let Some(JobState::Receiving { received, expected: Some(expected) }) = pipeline.jobs.get(&id).map(|job| job.state) {
+        if received + images > expected {
+            let err = format!("El trabajo espera {} imágenes más y se enviaron {}", expected - received, images);
+            return Ok(HttpResponse::Conflict().body(err));
+        }
+    }
 
-                if resp_info.status().is_success() {
-                    let task_info: serde_json::Value = resp_info.json().await.unwrap();
-                    let status_code = task_info["status"]["code"].as_i64().unwrap_or(0);
-                    
-                    match status_code {
-                        20 => {
-                            println!("La tarea sigue en desarrollo.");
-                        },
-                        40 => {
-                            println!("La tarea ha sido completada con éxito.");
-                            task_complete = true;
-                        },
-                    
-                        _ => {
-                            println!("La tarea ha finalizado con un estado desconocido.");
-                            break; // Salir del bucle si el estado no es reconocido
-                        }
-                    }
-                } else {
-                    println!("Error al obtener información de la tarea: {}", resp_info.status());
+    // Los archivos de datos van primero: la última imagen puede encolar el trabajo,
+    // y después ya no se aceptan archivos.
+    files.sort_by_key(|(_, kind, _, _)| *kind == FormField::Image);
+    let mut added = Vec::new();
+    let mut queued = false;
+    for (path, kind, filename, content_type) in &files {
+        let image = pipeline.add_image(&id, path, filename.as_deref(), Some(content_type))?;
+        let name = image.name.clone();
+        let received = if *kind == FormField::Image {
+            pipeline.jobs.receive_image(&id, image)
+        } else {
+            pipeline.jobs.receive_file(&id, image).then_some(false)
+        };
+        match received {
+            Some(received_all) => queued |= received_all,
+            // El trabajo se confirmó o canceló mientras llegaba la imagen (o lo
+            // encoló otro lote enviado a la vez).
+            None => {
+                let _ = fs::remove_file(pipeline.images_dir(&id).join(&name));
+                if queued {
+                    tokio::spawn(pipeline.get_ref().clone().run(id.clone()));
                 }
+                return Ok(HttpResponse::Conflict().body("El trabajo ya no recibe imágenes"));
             }
+        }
+        added.push(name);
+    }
 
-            // 5. Descargar el archivo all.zip
-            let download_url = format!("http://localhost:3000/task/{}/download/all.zip", token);
-            let response = client.get(&download_url).send().await.unwrap();
-            let bytes = response.bytes().await.unwrap();
-            fs::write("/home/hechicero/Downloads/all.zip", &bytes).unwrap();
+    if queued {
+        tokio::spawn(pipeline.get_ref().clone().run(id));
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "images": added })))
+}
 
-            println!("Archivo all.zip descargado con éxito!");
+// Endpoint para quitar una imagen de un trabajo que todavía no se confirmó.
+async fn delete_image(pipeline: web::Data<Pipeline>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (id, name) = path.into_inner();
+    if let Some(response) = check_receiving(&pipeline, &id) {
+        return response;
+    }
+    // Solo nombres de archivo, nunca rutas.
+    if std::path::Path::new(&name).file_name() != Some(std::ffi::OsStr::new(&name)) {
+        return HttpResponse::NotFound().body("Imagen no encontrada");
+    }
+    // Primero se quita del trabajo, si todavía recibe imágenes, y después del disco.
+    let counted = FormField::for_file_name(&name) == FormField::Image;
+    match pipeline.jobs.unreceive_image(&id, &name, counted) {
+        Some(true) => {}
+        Some(false) => return HttpResponse::NotFound().body("Imagen no encontrada"),
+        None => return HttpResponse::Conflict().body("El trabajo ya no recibe imágenes"),
+    }
+    match fs::remove_file(pipeline.images_dir(&id).join(&name)) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => HttpResponse::NoContent().finish(),
+        Err(err) => HttpResponse::InternalServerError().body(format!("Error al eliminar la imagen: {}", err)),
+    }
+}
 
-            // 6. Eliminar la tarea
-            let remove_url = "http://localhost:3000/task/remove";
-            let remove_body = serde_json::json!({
-                "uuid": token
-            });
-            let resp_remove = client.post(remove_url)
-                .json(&remove_body)
-                .send()
-                .await
-                .unwrap();
-            println!("{:#?}", resp_remove.text().await.unwrap());
+// Endpoint para confirmar un trabajo en `receiving` con las imágenes recibidas y
+// encolarlo. Si al crearlo se indicó `images`, deben haber llegado todas.
+async fn commit_job(pipeline: web::Data<Pipeline>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    if pipeline.jobs.get(&id).is_none() {
+        return HttpResponse::NotFound().body("Trabajo no encontrado");
+    }
+    if let Some(response) = queue_full(&pipeline) {
+        return response;
+    }
+    match pipeline.jobs.commit(&id) {
+        Ok(()) => {
+            tokio::spawn(pipeline.get_ref().clone().run(id.clone()));
+            HttpResponse::Accepted().json(serde_json::json!({ "job_id": id }))
+        }
+        Err(err) => HttpResponse::Conflict().body(err),
+    }
+}
 
-            // Al final, detener el contenedor
-            stop_container(&container_id).unwrap();
+// Endpoint para reenviar un trabajo cuyo nodo se perdió, usando las imágenes
+// conservadas en el servidor.
+async fn resubmit_job(pipeline: web::Data<Pipeline>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    if pipeline.jobs.get(&id).is_none() {
+        return HttpResponse::NotFound().body("Trabajo no encontrado");
+    }
+    match pipeline.jobs.resubmit(&id) {
+        Ok(()) => {
+            tokio::spawn(pipeline.get_ref().clone().run(id.clone()));
+            HttpResponse::Accepted().json(serde_json::json!({ "job_id": id }))
+        }
+        Err(err) => HttpResponse::Conflict().body(err),
+    }
+}
 
-            Ok(HttpResponse::Ok().body("Proceso de reconstrucción completado con éxito"))
+// Endpoint para cancelar un trabajo en curso. La tarea de fondo corta la subida o
+// cancela la tarea en NodeODM y detiene el contenedor del trabajo, si tiene uno.
+async fn cancel_job(pipeline: web::Data<Pipeline>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    let job = match pipeline.jobs.get(&id) {
+        Some(job) => job,
+        None => return HttpResponse::NotFound().body("Trabajo no encontrado"),
+    };
+    match job.state {
+        JobState::Completed | JobState::Failed | JobState::Canceled => {
+            HttpResponse::Conflict().body("El trabajo ya terminó")
+        }
+        _ if pipeline.cancel(&id) => HttpResponse::Accepted().json(serde_json::json!({ "job_id": id })),
+        // Sin tarea de fondo (p. ej. `node_lost`) no hay nada que detener.
+        _ => {
+            pipeline.jobs.set_state(&id, JobState::Canceled);
+            pipeline.webhooks.job_finished(&id);
+            HttpResponse::Accepted().json(serde_json::json!({ "job_id": id }))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RestartBody {
+    #[serde(default)]
+    options: Vec<TaskOption>,
+}
+
+// Endpoint para reiniciar un trabajo fallido o cancelado. El cuerpo opcional
+// `{"options": [...]}` reemplaza las opciones con el mismo nombre; el resto se conserva.
+async fn restart_job(pipeline: web::Data<Pipeline>, path: web::Path<String>, body: web::Bytes) -> HttpResponse {
+    let id = path.into_inner();
+    let job = match pipeline.jobs.get(&id) {
+        Some(job) => job,
+        None => return HttpResponse::NotFound().body("Trabajo no encontrado"),
+    };
+    if !matches!(job.state, JobState::Failed | JobState::Canceled) {
+        return HttpResponse::Conflict().body("Solo se pueden reiniciar trabajos fallidos o cancelados");
+    }
+    let overrides = if body.is_empty() {
+        Vec::new()
+    } else {
+        match serde_json::from_slice::<RestartBody>(&body) {
+            Ok(body) => body.options,
+            Err(err) => return HttpResponse::BadRequest().body(format!("Cuerpo inválido: {}", err)),
+        }
+    };
+
+    let task_options = options::merge_options(job.options, overrides);
+    if let Some(schema) = pipeline.schema.load(&pipeline.nodes).await {
+        if let Err(err) = options::validate_options(&schema, &task_options) {
+            return HttpResponse::BadRequest().body(err);
+        }
+    }
+    if let Err(err) = pipeline.jobs.restart(&id, task_options) {
+        return HttpResponse::Conflict().body(err);
+    }
+    tokio::spawn(pipeline.get_ref().clone().restart(id.clone()));
+    HttpResponse::Accepted().json(serde_json::json!({ "job_id": id }))
+}
+
+// Endpoint para eliminar un trabajo terminado junto con sus imágenes y resultados.
+async fn delete_job(pipeline: web::Data<Pipeline>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    match pipeline.jobs.get(&id) {
+        Some(job) if job.state.is_terminal() => match pipeline.delete(&id).await {
+            Ok(()) => HttpResponse::NoContent().finish(),
+            Err(err) => HttpResponse::InternalServerError().body(format!("Error al eliminar el trabajo: {}", err)),
         },
-        Err(_) => Ok(HttpResponse::InternalServerError().body("Error al iniciar el contenedor")),
+        Some(_) => HttpResponse::Conflict().body("El trabajo sigue en curso; cancélelo antes de eliminarlo"),
+        None => HttpResponse::NotFound().body("Trabajo no encontrado"),
+    }
+}
+
+// Endpoint para descargar un resultado del trabajo (p. ej. `all.zip`) sin cargarlo
+// completo en memoria.
+async fn download_artifact(jobs: web::Data<JobStore>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (id, name) = path.into_inner();
+    let job = match jobs.get(&id) {
+        Some(job) => job,
+        None => return HttpResponse::NotFound().body("Trabajo no encontrado"),
+    };
+    let artifact = job.artifacts.iter().map(PathBuf::from).find(|p| p.file_name().is_some_and(|n| n == name.as_str()));
+    let artifact = match artifact {
+        Some(artifact) => artifact,
+        None => return HttpResponse::NotFound().body("Resultado no encontrado"),
+    };
+    let file = match tokio::fs::File::open(&artifact).await {
+        Ok(file) => file,
+        Err(err) => return HttpResponse::InternalServerError().body(format!("Error al abrir el resultado: {}", err)),
+    };
+    let length = match file.metadata().await {
+        Ok(metadata) => metadata.len(),
+        Err(err) => return HttpResponse::InternalServerError().body(format!("Error al abrir el resultado: {}", err)),
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", name)))
+        .no_chunking(length)
+        .streaming(pipeline::file_chunks(file))
+}
+
+// Endpoint para consultar el estado de un trabajo.
+async fn get_job(jobs: web::Data<JobStore>, path: web::Path<String>) -> HttpResponse {
+    match jobs.get(&path.into_inner()) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().body("Trabajo no encontrado"),
     }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
+    let config = Config::load()?;
+    let data_dir = PathBuf::from(&config.data_dir);
+    std::fs::create_dir_all(&data_dir)?;
+    let jobs = JobStore::open(&data_dir.join("jobs.db")).map_err(std::io::Error::other)?;
+    let settings = SettingsStore::open(&data_dir.join("jobs.db")).map_err(std::io::Error::other)?;
+    let schema = OptionSchemaCache::default();
+    let presets = PresetStore::new(config.presets, &settings);
+    let containers = ContainerManager::new(config.container, Duration::from_secs(config.node_ready_timeout_secs));
+    containers
+        .start()
+        .await
+        .map_err(std::io::Error::other)?;
+    let nodes = NodeRegistry::new(config.nodes, config.circuit_breaker);
+    if let Some(local) = containers.shared_node() {
+        nodes.put(local);
+    }
+    nodes.apply_saved(&settings);
+    if nodes.list().is_empty() && containers.mode() != ContainerMode::PerJob {
+        println!("No hay nodos de NodeODM registrados; se pueden agregar con PUT /nodes/{{name}}.");
+    }
+    // Cargar el esquema de opciones para validar desde la primera petición.
+    schema.load(&nodes).await;
+    let queue = JobQueue::new(config.queue);
+    let webhooks = Webhooks::new(config.webhooks, jobs.clone(), &config.public_url);
+    let uploads = TusUploads::new(data_dir.join("tus"));
+    let pipeline = Pipeline {
+        jobs: jobs.clone(),
+        schema: schema.clone(),
+        containers: containers.clone(),
+        nodes: nodes.clone(),
+        queue: queue.clone(),
+        cancellations: Cancellations::default(),
+        webhooks,
+        node_ready_timeout: Duration::from_secs(config.node_ready_timeout_secs),
+        data_dir,
+        max_poll_failures: config.max_poll_failures,
+    };
+    // Retomar los trabajos que quedaron en curso antes de aceptar peticiones nuevas.
+    pipeline.recover().await;
+
+    let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
-            .allowed_methods(vec!["GET", "POST"]);
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "PATCH", "HEAD", "OPTIONS"])
+            // Los clientes tus envían y leen sus propios encabezados (`Upload-Offset`, `Location`, ...).
+            .allow_any_header()
+            .expose_any_header();
 
             App::new()
             .app_data(web::PayloadConfig::default().limit(1024 * 1024 * 10))
+            .app_data(web::Data::new(jobs.clone()))
+            .app_data(web::Data::new(schema.clone()))
+            .app_data(web::Data::new(presets.clone()))
+            .app_data(web::Data::new(pipeline.clone()))
+            .app_data(web::Data::new(nodes.clone()))
+            .app_data(web::Data::new(queue.clone()))
+            .app_data(web::Data::new(uploads.clone()))
+            .app_data(web::Data::new(settings.clone()))
         
             .wrap(cors)
             .service(web::resource("/start_reconstruction").route(web::post().to(start_reconstruction)))
+            .service(web::resource("/queue").route(web::get().to(queue::get_queue)))
+            .service(web::resource("/jobs").route(web::post().to(create_job)))
+            .service(
+                web::resource("/uploads")
+                    .route(web::post().to(tus::create))
+                    .route(web::method(Method::OPTIONS).to(tus::options)),
+            )
+            .service(
+                web::resource("/uploads/{id}")
+                    .route(web::head().to(tus::head))
+                    .route(web::patch().to(tus::patch)),
+            )
+            .service(web::resource("/events").route(web::get().to(events::job_events)))
+            .service(
+                web::resource("/jobs/{id}")
+                    .route(web::get().to(get_job))
+                    .route(web::delete().to(delete_job)),
+            )
+            .service(web::resource("/jobs/{id}/images").route(web::post().to(add_images)))
+            .service(web::resource("/jobs/{id}/images/{name}").route(web::delete().to(delete_image)))
+            .service(web::resource("/jobs/{id}/commit").route(web::post().to(commit_job)))
+            .service(web::resource("/jobs/{id}/resubmit").route(web::post().to(resubmit_job)))
+            .service(web::resource("/jobs/{id}/cancel").route(web::post().to(cancel_job)))
+            .service(web::resource("/jobs/{id}/log").route(web::get().to(console::get_log)))
+            .service(web::resource("/jobs/{id}/artifacts/{name}").route(web::get().to(download_artifact)))
+            .service(web::resource("/jobs/{id}/log/stream").route(web::get().to(console::stream_log)))
+            .service(web::resource("/jobs/{id}/restart").route(web::post().to(restart_job)))
+            .service(web::resource("/presets").route(web::get().to(presets::list_presets)))
+            .service(
+                web::resource("/presets/{name}")
+                    .route(web::get().to(presets::get_preset))
+                    .route(web::put().to(presets::put_preset))
+                    .route(web::delete().to(presets::delete_preset)),
+            )
+            .service(web::resource("/nodes").route(web::get().to(nodes::list_nodes)))
+            .service(
+                web::resource("/nodes/{name}")
+                    .route(web::get().to(nodes::get_node))
+                    .route(web::put().to(nodes::put_node))
+                    .route(web::delete().to(nodes::delete_node)),
+            )
     })
     .bind("127.0.0.1:3001")?
     .run()
-    .await
+    .await;
+
+    // Al apagar el servidor, detener el contenedor compartido si lo iniciamos.
+    containers.shutdown().await;
+    server
 }
 
 
@@ -0,0 +1,378 @@
+use reqwest::multipart::{Form, Part};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+// Tiempo máximo para conectar con el nodo.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Tiempo máximo de las consultas cortas (estado, consola, información). Las
+// subidas y descargas no tienen límite porque dependen del tamaño de los archivos.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Errores que puede devolver el cliente de NodeODM.
+#[derive(Debug)]
+pub enum NodeOdmError {
+    // Error de red o de la petición HTTP.
+    Http(reqwest::Error),
+    // NodeODM respondió con `{"error": "..."}`.
+    Api(String),
+    // NodeODM respondió con un código HTTP no exitoso sin mensaje de error.
+    Status(StatusCode),
+    // La respuesta no tiene el formato esperado.
+    Decode(serde_json::Error),
+    // Error al escribir un archivo descargado.
+    Io(std::io::Error),
+    // El nodo no respondió antes del tiempo límite; incluye el último error.
+    NotReady(Duration, Box<NodeOdmError>),
+}
+
+impl fmt::Display for NodeOdmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeOdmError::Http(err) => write!(f, "error de conexión con NodeODM: {}", err),
+            NodeOdmError::Api(msg) => write!(f, "NodeODM devolvió un error: {}", msg),
+            NodeOdmError::Status(status) => write!(f, "NodeODM respondió con estado {}", status),
+            NodeOdmError::Decode(err) => write!(f, "respuesta inesperada de NodeODM: {}", err),
+            NodeOdmError::Io(err) => write!(f, "error de escritura: {}", err),
+            NodeOdmError::NotReady(timeout, err) => {
+                write!(f, "NodeODM no estuvo disponible en {} s ({})", timeout.as_secs(), err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NodeOdmError {}
+
+impl From<reqwest::Error> for NodeOdmError {
+    fn from(err: reqwest::Error) -> Self {
+        NodeOdmError::Http(err)
+    }
+}
+
+impl From<std::io::Error> for NodeOdmError {
+    fn from(err: std::io::Error) -> Self {
+        NodeOdmError::Io(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, NodeOdmError>;
+
+// Opción de procesamiento de ODM, p. ej. `{"name": "dsm", "value": true}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TaskOption {
+    pub name: String,
+    pub value: serde_json::Value,
+}
+
+// Parámetros de `POST /task/new/init`.
+#[derive(Clone, Debug, Default)]
+pub struct NewTaskRequest {
+    pub name: Option<String>,
+    pub options: Vec<TaskOption>,
+    pub webhook: Option<String>,
+    pub skip_post_processing: bool,
+    pub outputs: Option<Vec<String>>,
+}
+
+// Respuesta de `/task/new/init` y `/task/new/commit/{uuid}`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TaskUuid {
+    pub uuid: String,
+}
+
+// Respuesta de las operaciones que solo confirman éxito (upload, cancel, restart, remove).
+#[derive(Clone, Debug, Deserialize)]
+pub struct SuccessResponse {
+    pub success: bool,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+// Código de estado de una tarea en NodeODM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum TaskStatus {
+    Queued,
+    Running,
+    Failed,
+    Completed,
+    Canceled,
+    Unknown(i64),
+}
+
+impl From<i64> for TaskStatus {
+    fn from(code: i64) -> Self {
+        match code {
+            10 => TaskStatus::Queued,
+            20 => TaskStatus::Running,
+            30 => TaskStatus::Failed,
+            40 => TaskStatus::Completed,
+            50 => TaskStatus::Canceled,
+            other => TaskStatus::Unknown(other),
+        }
+    }
+}
+
+impl From<TaskStatus> for i64 {
+    fn from(status: TaskStatus) -> Self {
+        match status {
+            TaskStatus::Queued => 10,
+            TaskStatus::Running => 20,
+            TaskStatus::Failed => 30,
+            TaskStatus::Completed => 40,
+            TaskStatus::Canceled => 50,
+            TaskStatus::Unknown(code) => code,
+        }
+    }
+}
+
+// Estado de una tarea tal como lo reporta NodeODM.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskStatusInfo {
+    pub code: TaskStatus,
+    pub error_message: Option<String>,
+}
+
+// Respuesta de `GET /task/{uuid}/info`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskInfo {
+    pub uuid: String,
+    pub name: Option<String>,
+    pub date_created: Option<i64>,
+    pub processing_time: Option<i64>,
+    pub status: TaskStatusInfo,
+    #[serde(default)]
+    pub options: Vec<TaskOption>,
+    #[serde(default)]
+    pub images_count: usize,
+    #[serde(default)]
+    pub progress: f64,
+}
+
+// Respuesta de `GET /info`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    pub version: String,
+    pub task_queue_count: usize,
+    pub total_memory: Option<u64>,
+    pub available_memory: Option<u64>,
+    pub cpu_cores: Option<usize>,
+    pub max_images: Option<usize>,
+    pub max_parallel_tasks: Option<usize>,
+    pub engine: Option<String>,
+    pub engine_version: Option<String>,
+}
+
+// Definición de una opción de procesamiento, de `GET /options`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OptionSchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: serde_json::Value,
+    #[serde(default)]
+    pub domain: serde_json::Value,
+    #[serde(default)]
+    pub help: String,
+}
+
+// Cliente tipado para la API REST de NodeODM.
+#[derive(Clone, Debug)]
+pub struct NodeOdmClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl NodeOdmClient {
+    pub fn new(base_url: &str) -> Self {
+        NodeOdmClient {
+            http: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: None,
+        }
+    }
+
+    // Token de autenticación que se envía como `?token=` en cada petición.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.authorize(self.http.get(format!("{}{}", self.base_url, path)))
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        self.authorize(self.http.post(format!("{}{}", self.base_url, path)))
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.query(&[("token", token)]),
+            None => request,
+        }
+    }
+
+    // Decodifica la respuesta, convirtiendo `{"error": "..."}` en `NodeOdmError::Api`.
+    async fn parse<T: DeserializeOwned>(resp: Response) -> Result<T> {
+        let status = resp.status();
+        let body = resp.bytes().await?;
+        if let Ok(err) = serde_json::from_slice::<ErrorResponse>(&body) {
+            return Err(NodeOdmError::Api(err.error));
+        }
+        if !status.is_success() {
+            return Err(NodeOdmError::Status(status));
+        }
+        serde_json::from_slice(&body).map_err(NodeOdmError::Decode)
+    }
+
+    async fn post_uuid(&self, path: &str, uuid: &str) -> Result<SuccessResponse> {
+        let resp = self.post(path).form(&[("uuid", uuid)]).timeout(REQUEST_TIMEOUT).send().await?;
+        Self::parse(resp).await
+    }
+
+    // POST /task/new/init
+    pub async fn init_task(&self, request: &NewTaskRequest) -> Result<TaskUuid> {
+        let mut fields = vec![(
+            "options",
+            serde_json::to_string(&request.options).map_err(NodeOdmError::Decode)?,
+        )];
+        if let Some(name) = &request.name {
+            fields.push(("name", name.clone()));
+        }
+        if let Some(webhook) = &request.webhook {
+            fields.push(("webhook", webhook.clone()));
+        }
+        if request.skip_post_processing {
+            fields.push(("skipPostProcessing", "true".to_string()));
+        }
+        if let Some(outputs) = &request.outputs {
+            fields.push(("outputs", serde_json::to_string(outputs).map_err(NodeOdmError::Decode)?));
+        }
+
+        let resp = self.post("/task/new/init").form(&fields).timeout(REQUEST_TIMEOUT).send().await?;
+        Self::parse(resp).await
+    }
+
+    // POST /task/new/upload/{uuid}
+    pub async fn upload(&self, uuid: &str, image: Part) -> Result<SuccessResponse> {
+        let form = Form::new().part("images", image);
+        let resp = self.post(&format!("/task/new/upload/{}", uuid)).multipart(form).send().await?;
+        Self::parse(resp).await
+    }
+
+    // POST /task/new/commit/{uuid}
+    pub async fn commit(&self, uuid: &str) -> Result<TaskUuid> {
+        let resp = self.post(&format!("/task/new/commit/{}", uuid)).timeout(REQUEST_TIMEOUT).send().await?;
+        Self::parse(resp).await
+    }
+
+    // GET /task/{uuid}/info
+    pub async fn task_info(&self, uuid: &str) -> Result<TaskInfo> {
+        let resp = self.get(&format!("/task/{}/info", uuid)).timeout(REQUEST_TIMEOUT).send().await?;
+        Self::parse(resp).await
+    }
+
+    // GET /task/{uuid}/output?line=N — líneas de consola a partir de `line`.
+    // Un valor negativo devuelve las últimas `-line` líneas.
+    pub async fn output(&self, uuid: &str, line: i64) -> Result<Vec<String>> {
+        let resp = self
+            .get(&format!("/task/{}/output", uuid))
+            .query(&[("line", line)])
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?;
+        Self::parse(resp).await
+    }
+
+    // GET /task/{uuid}/download/{asset} — descarga el archivo sin cargarlo completo en memoria.
+    pub async fn download(&self, uuid: &str, asset: &str, destination: &Path) -> Result<()> {
+        let mut resp = self.get(&format!("/task/{}/download/{}", uuid, asset)).send().await?;
+        let is_json = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        if !resp.status().is_success() || is_json {
+            return Self::parse::<()>(resp).await;
+        }
+
+        let mut file = tokio::fs::File::create(destination).await?;
+        while let Some(chunk) = resp.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+
+    // POST /task/cancel
+    pub async fn cancel(&self, uuid: &str) -> Result<SuccessResponse> {
+        self.post_uuid("/task/cancel", uuid).await
+    }
+
+    // POST /task/restart — `options` reemplaza las opciones de la tarea si se indica.
+    pub async fn restart(&self, uuid: &str, options: Option<&[TaskOption]>) -> Result<SuccessResponse> {
+        let mut fields = vec![("uuid", uuid.to_string())];
+        if let Some(options) = options {
+            fields.push(("options", serde_json::to_string(options).map_err(NodeOdmError::Decode)?));
+        }
+        let resp = self.post("/task/restart").form(&fields).timeout(REQUEST_TIMEOUT).send().await?;
+        Self::parse(resp).await
+    }
+
+    // POST /task/remove
+    pub async fn remove(&self, uuid: &str) -> Result<SuccessResponse> {
+        self.post_uuid("/task/remove", uuid).await
+    }
+
+    // GET /info
+    pub async fn info(&self) -> Result<NodeInfo> {
+        let resp = self.get("/info").timeout(REQUEST_TIMEOUT).send().await?;
+        Self::parse(resp).await
+    }
+
+    // Consulta `/info` con espera exponencial hasta que el nodo responda o se
+    // agote `timeout`.
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<NodeInfo> {
+        let deadline = Instant::now() + timeout;
+        let mut delay = Duration::from_millis(250);
+        loop {
+            match self.info().await {
+                Ok(info) => return Ok(info),
+                Err(err) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(NodeOdmError::NotReady(timeout, Box::new(err)));
+                    }
+                    tokio::time::sleep(delay.min(deadline - now)).await;
+                    delay = (delay * 2).min(Duration::from_secs(5));
+                }
+            }
+        }
+    }
+
+    // GET /options
+    pub async fn options(&self) -> Result<Vec<OptionSchema>> {
+        let resp = self.get("/options").timeout(REQUEST_TIMEOUT).send().await?;
+        Self::parse(resp).await
+    }
+}
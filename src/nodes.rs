@@ -0,0 +1,360 @@
+use crate::settings::{self, SettingsStore};
+use actix_web::{web, HttpResponse};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use webodm_client::nodeodm::{NodeInfo, NodeOdmClient};
+
+// Espera entre intentos cuando ningún nodo puede recibir el trabajo todavía.
+const SCHEDULE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+// Nodo de procesamiento NodeODM registrado en el servicio.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NodeConfig {
+    pub name: String,
+    pub url: String,
+    // No se incluye en las respuestas de la API.
+    #[serde(default, skip_serializing)]
+    pub token: Option<String>,
+    // Máximo de trabajos de este servicio en el nodo a la vez; `None` es sin límite.
+    #[serde(default)]
+    pub max_tasks: Option<usize>,
+}
+
+impl NodeConfig {
+    pub fn client(&self) -> NodeOdmClient {
+        NodeOdmClient::new(&self.url).with_token(self.token.clone())
+    }
+}
+
+// Parámetros del cortocircuito por nodo: tras `failures` errores consecutivos el
+// nodo se marca como no disponible y se vuelve a probar pasados `cooldown_secs`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    pub failures: u32,
+    pub cooldown_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig { failures: 3, cooldown_secs: 60 }
+    }
+}
+
+// Nodo asignado a un trabajo. `node_name` existe cuando viene del registro;
+// `container_id` y `host_port`, cuando es un contenedor por trabajo.
+pub struct NodeLease {
+    pub node: NodeOdmClient,
+    pub node_name: Option<String>,
+    pub container_id: Option<String>,
+    pub host_port: Option<u16>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NodeStatus {
+    #[serde(flatten)]
+    pub config: NodeConfig,
+    // Trabajos de este servicio que están usando el nodo.
+    pub active_tasks: usize,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    // Momento a partir del cual se vuelve a probar un nodo no disponible.
+    #[serde(skip)]
+    retry_at: Option<Instant>,
+}
+
+impl NodeStatus {
+    // Un nodo no disponible vuelve a ser candidato cuando termina su espera.
+    fn available(&self) -> bool {
+        self.healthy || self.retry_at.is_some_and(|at| Instant::now() >= at)
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.config.max_tasks.is_none_or(|max| self.active_tasks < max)
+    }
+}
+
+// Registro de nodos y planificador que reparte los trabajos entre ellos.
+#[derive(Clone, Default)]
+pub struct NodeRegistry {
+    nodes: Arc<RwLock<BTreeMap<String, NodeStatus>>>,
+    breaker: CircuitBreakerConfig,
+}
+
+impl NodeRegistry {
+    pub fn new(nodes: Vec<NodeConfig>, breaker: CircuitBreakerConfig) -> Self {
+        let registry = NodeRegistry { nodes: Arc::default(), breaker };
+        for node in nodes {
+            registry.put(node);
+        }
+        registry
+    }
+
+    pub fn list(&self) -> Vec<NodeStatus> {
+        self.nodes.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<NodeStatus> {
+        self.nodes.read().unwrap().get(name).cloned()
+    }
+
+    // Inserta o reemplaza un nodo, conservando su cuenta de trabajos activos.
+    // Devuelve `true` si ya existía.
+    pub fn put(&self, config: NodeConfig) -> bool {
+        let mut nodes = self.nodes.write().unwrap();
+        let active_tasks = nodes.get(&config.name).map_or(0, |n| n.active_tasks);
+        let status = NodeStatus { config, active_tasks, healthy: true, consecutive_failures: 0, retry_at: None };
+        nodes.insert(status.config.name.clone(), status).is_some()
+    }
+
+    pub fn record_success(&self, name: &str) {
+        if let Some(node) = self.nodes.write().unwrap().get_mut(name) {
+            if !node.healthy {
+                println!("El nodo {} vuelve a estar disponible", name);
+            }
+            node.healthy = true;
+            node.consecutive_failures = 0;
+            node.retry_at = None;
+        }
+    }
+
+    // Cuenta un error de comunicación con el nodo. Devuelve `true` si el nodo
+    // quedó marcado como no disponible.
+    pub fn record_failure(&self, name: &str) -> bool {
+        let mut nodes = self.nodes.write().unwrap();
+        let node = match nodes.get_mut(name) {
+            Some(node) => node,
+            None => return false,
+        };
+        node.consecutive_failures += 1;
+        if node.consecutive_failures >= self.breaker.failures {
+            if node.healthy {
+                println!("El nodo {} se marca como no disponible tras {} fallos", name, node.consecutive_failures);
+            }
+            node.healthy = false;
+            node.retry_at = Some(Instant::now() + Duration::from_secs(self.breaker.cooldown_secs));
+        }
+        !node.healthy
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.nodes.write().unwrap().remove(name).is_some()
+    }
+
+    // Aplica los nodos registrados o eliminados por la API en ejecuciones anteriores.
+    pub fn apply_saved(&self, settings: &SettingsStore) {
+        for (name, body) in settings.load::<NodeBody>(settings::NODES) {
+            match body {
+                Some(body) => {
+                    self.put(body.into_config(name));
+                }
+                None => {
+                    self.remove(&name);
+                }
+            }
+        }
+    }
+
+    // Espera hasta que algún nodo pueda recibir `image_count` imágenes y lo reserva.
+    // Los nodos de `exclude` no se consideran. Falla si ningún nodo acepta esa
+    // cantidad de imágenes o si ninguno responde antes de `unreachable_timeout`.
+    pub async fn acquire(
+        &self,
+        image_count: usize,
+        exclude: &[String],
+        unreachable_timeout: Duration,
+    ) -> Result<NodeLease, String> {
+        let started = Instant::now();
+        loop {
+            match self.select(image_count, exclude).await {
+                Selection::Node(config) => {
+                    if self.reserve(&config.name) {
+                        println!("Trabajo asignado al nodo {} ({})", config.name, config.url);
+                        return Ok(NodeLease {
+                            node: config.client(),
+                            node_name: Some(config.name),
+                            container_id: None,
+                            host_port: None,
+                        });
+                    }
+                }
+                Selection::Busy => {}
+                Selection::Unreachable if started.elapsed() >= unreachable_timeout => {
+                    return Err("Ningún nodo de NodeODM está disponible".to_string());
+                }
+                Selection::Unreachable => {}
+                Selection::TooManyImages => {
+                    return Err(format!("Ningún nodo acepta {} imágenes", image_count));
+                }
+            }
+            tokio::time::sleep(SCHEDULE_RETRY_INTERVAL).await;
+        }
+    }
+
+    // Vuelve a tomar el nodo de un trabajo recuperado tras un reinicio. Cuenta el
+    // trabajo aunque el nodo esté en su límite, porque la tarea ya está en él.
+    pub fn attach(&self, name: &str) -> Option<NodeLease> {
+        let mut nodes = self.nodes.write().unwrap();
+        let node = nodes.get_mut(name)?;
+        node.active_tasks += 1;
+        Some(NodeLease {
+            node: node.config.client(),
+            node_name: Some(name.to_string()),
+            container_id: None,
+            host_port: None,
+        })
+    }
+
+    // Reserva un lugar en el nodo si todavía tiene capacidad.
+    fn reserve(&self, name: &str) -> bool {
+        let mut nodes = self.nodes.write().unwrap();
+        match nodes.get_mut(name) {
+            Some(node) if node.has_capacity() => {
+                node.active_tasks += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Libera el lugar reservado por `acquire`.
+    pub fn release(&self, name: &str) {
+        if let Some(node) = self.nodes.write().unwrap().get_mut(name) {
+            node.active_tasks = node.active_tasks.saturating_sub(1);
+        }
+    }
+
+    // Consulta `/info` de los nodos disponibles con capacidad libre y elige el menos
+    // cargado (menos tareas en cola y, a igualdad, más memoria disponible).
+    async fn select(&self, image_count: usize, exclude: &[String]) -> Selection {
+        let nodes: Vec<NodeStatus> = self
+            .list()
+            .into_iter()
+            .filter(|n| n.available() && !exclude.contains(&n.config.name))
+            .collect();
+        let busy = nodes.iter().any(|n| !n.has_capacity());
+        let candidates: Vec<NodeConfig> = nodes.into_iter().filter(|n| n.has_capacity()).map(|n| n.config).collect();
+
+        let infos = join_all(candidates.iter().map(|c| async move { c.client().info().await })).await;
+
+        let mut reachable = false;
+        let mut unreachable = false;
+        let mut best: Option<(NodeConfig, NodeInfo)> = None;
+        for (config, info) in candidates.into_iter().zip(infos) {
+            let info = match info {
+                Ok(info) => info,
+                Err(err) => {
+                    println!("El nodo {} no responde: {}", config.name, err);
+                    self.record_failure(&config.name);
+                    unreachable = true;
+                    continue;
+                }
+            };
+            self.record_success(&config.name);
+            reachable = true;
+            if info.max_images.is_some_and(|max| image_count > max) {
+                continue;
+            }
+            let better = match &best {
+                Some((_, current)) => load_key(&info) < load_key(current),
+                None => true,
+            };
+            if better {
+                best = Some((config, info));
+            }
+        }
+
+        match best {
+            Some((config, _)) => Selection::Node(config),
+            None if busy => Selection::Busy,
+            // Un nodo caído podría aceptar el trabajo cuando vuelva.
+            None if reachable && !unreachable => Selection::TooManyImages,
+            None => Selection::Unreachable,
+        }
+    }
+}
+
+enum Selection {
+    Node(NodeConfig),
+    // Todos los nodos están en su límite de trabajos.
+    Busy,
+    // Ningún nodo disponible respondió a `/info`.
+    Unreachable,
+    // Los nodos que respondieron no aceptan tantas imágenes.
+    TooManyImages,
+}
+
+fn load_key(info: &NodeInfo) -> (usize, std::cmp::Reverse<u64>) {
+    (info.task_queue_count, std::cmp::Reverse(info.available_memory.unwrap_or(0)))
+}
+
+// Cuerpo de `PUT /nodes/{name}`; también es lo que se guarda de cada nodo.
+#[derive(Deserialize, Serialize)]
+pub struct NodeBody {
+    pub url: String,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub max_tasks: Option<usize>,
+}
+
+impl NodeBody {
+    fn into_config(self, name: String) -> NodeConfig {
+        NodeConfig { name, url: self.url, token: self.token, max_tasks: self.max_tasks }
+    }
+}
+
+// GET /nodes
+pub async fn list_nodes(nodes: web::Data<NodeRegistry>) -> HttpResponse {
+    HttpResponse::Ok().json(nodes.list())
+}
+
+// GET /nodes/{name} — incluye el `/info` actual del nodo si responde.
+pub async fn get_node(nodes: web::Data<NodeRegistry>, path: web::Path<String>) -> HttpResponse {
+    let node = match nodes.get(&path.into_inner()) {
+        Some(node) => node,
+        None => return HttpResponse::NotFound().body("Nodo no encontrado"),
+    };
+    let info = node.config.client().info().await;
+    HttpResponse::Ok().json(serde_json::json!({
+        "node": node,
+        "info": info.as_ref().ok(),
+        "error": info.as_ref().err().map(|e| e.to_string()),
+    }))
+}
+
+// PUT /nodes/{name} — registra o reemplaza un nodo.
+pub async fn put_node(
+    nodes: web::Data<NodeRegistry>,
+    settings: web::Data<SettingsStore>,
+    path: web::Path<String>,
+    body: web::Json<NodeBody>,
+) -> HttpResponse {
+    let body = body.into_inner();
+    let name = path.into_inner();
+    settings.save(settings::NODES, &name, &body);
+    let config = body.into_config(name);
+    if nodes.put(config.clone()) {
+        HttpResponse::Ok().json(config)
+    } else {
+        HttpResponse::Created().json(config)
+    }
+}
+
+// DELETE /nodes/{name} — los trabajos en curso en el nodo continúan.
+pub async fn delete_node(
+    nodes: web::Data<NodeRegistry>,
+    settings: web::Data<SettingsStore>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    if nodes.remove(&name) {
+        settings.delete(settings::NODES, &name);
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().body("Nodo no encontrado")
+    }
+}
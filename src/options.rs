@@ -0,0 +1,258 @@
+use crate::nodes::NodeRegistry;
+use serde_json::Value;
+use std::sync::{Arc, RwLock};
+use webodm_client::nodeodm::{OptionSchema, TaskOption};
+
+// Última definición de opciones obtenida de `GET /options`. Permite validar en el
+// endpoint antes de crear el trabajo; se carga al iniciar y la tarea de fondo la
+// actualiza al conectarse al nodo.
+#[derive(Clone, Default)]
+pub struct OptionSchemaCache {
+    schema: Arc<RwLock<Option<Vec<OptionSchema>>>>,
+}
+
+impl OptionSchemaCache {
+    pub fn get(&self) -> Option<Vec<OptionSchema>> {
+        self.schema.read().unwrap().clone()
+    }
+
+    pub fn set(&self, schema: Vec<OptionSchema>) {
+        *self.schema.write().unwrap() = Some(schema);
+    }
+
+    // El esquema en caché o, si todavía no se conoce, el del primer nodo registrado
+    // que responda `GET /options`. `None` solo si ningún nodo responde (p. ej. en
+    // modo `per_job`, donde no hay nodo hasta que corre un trabajo).
+    pub async fn load(&self, nodes: &NodeRegistry) -> Option<Vec<OptionSchema>> {
+        if let Some(schema) = self.get() {
+            return Some(schema);
+        }
+        for node in nodes.list() {
+            match node.config.client().options().await {
+                Ok(schema) => {
+                    self.set(schema.clone());
+                    return Some(schema);
+                }
+                Err(err) => println!("No se pudieron leer las opciones del nodo {}: {}", node.config.name, err),
+            }
+        }
+        None
+    }
+}
+
+// Interpreta el campo `options` del formulario: un arreglo JSON de `{"name", "value"}`.
+pub fn parse_options(raw: &[u8]) -> Result<Vec<TaskOption>, String> {
+    serde_json::from_slice(raw).map_err(|e| format!("El campo options no es válido: {}", e))
+}
+
+// Combina dos listas de opciones; las de `overrides` reemplazan a las de `base`
+// con el mismo nombre.
+pub fn merge_options(mut base: Vec<TaskOption>, overrides: Vec<TaskOption>) -> Vec<TaskOption> {
+    for option in overrides {
+        match base.iter_mut().find(|o| o.name == option.name) {
+            Some(existing) => existing.value = option.value,
+            None => base.push(option),
+        }
+    }
+    base
+}
+
+// Verifica que cada opción exista en el esquema del nodo y que su valor respete
+// el tipo y el dominio declarados.
+pub fn validate_options(schema: &[OptionSchema], options: &[TaskOption]) -> Result<(), String> {
+    for option in options {
+        let definition = schema
+            .iter()
+            .find(|s| s.name == option.name)
+            .ok_or_else(|| format!("Opción desconocida: {}", option.name))?;
+
+        validate_value(definition, &option.value)
+            .map_err(|reason| format!("Valor inválido para {}: {}", option.name, reason))?;
+    }
+    Ok(())
+}
+
+fn validate_value(definition: &OptionSchema, value: &Value) -> Result<(), String> {
+    match definition.kind.as_str() {
+        "int" => {
+            let number = as_number(value).ok_or("se esperaba un entero")?;
+            if number.fract() != 0.0 {
+                return Err("se esperaba un entero".to_string());
+            }
+            check_domain(&definition.domain, number)
+        }
+        "float" => {
+            let number = as_number(value).ok_or("se esperaba un número")?;
+            check_domain(&definition.domain, number)
+        }
+        "bool" => match value {
+            Value::Bool(_) => Ok(()),
+            Value::String(s) if s == "true" || s == "false" => Ok(()),
+            _ => Err("se esperaba true o false".to_string()),
+        },
+        "enum" => {
+            let allowed = definition.domain.as_array().cloned().unwrap_or_default();
+            let text = as_text(value);
+            if allowed.iter().any(|a| as_text(a) == text) {
+                Ok(())
+            } else {
+                Err(format!("debe ser uno de {}", definition.domain))
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+// Revisa los dominios numéricos que publica NodeODM, p. ej. "positive integer",
+// "percent", "float: 0 <= x <= 10" o "float > 0.0".
+fn check_domain(domain: &Value, number: f64) -> Result<(), String> {
+    let domain = match domain.as_str() {
+        Some(domain) => domain.trim(),
+        None => return Ok(()),
+    };
+
+    let in_range = if domain.starts_with("positive") {
+        number >= 0.0
+    } else if domain.starts_with("negative") {
+        number <= 0.0
+    } else if domain == "percent" {
+        (0.0..=100.0).contains(&number)
+    } else if let Some((_, range)) = domain.split_once(':') {
+        // "float: 0 <= x <= 10"
+        let bounds: Vec<f64> = range.split("<=").filter_map(|p| p.trim().parse().ok()).collect();
+        match bounds.as_slice() {
+            [min, max] => *min <= number && number <= *max,
+            _ => true,
+        }
+    } else {
+        // "float > 0.0", "integer >= 1", ...
+        let parts: Vec<&str> = domain.split_whitespace().collect();
+        match parts.as_slice() {
+            [_, op, limit] => match limit.parse::<f64>() {
+                Ok(limit) => match *op {
+                    ">" => number > limit,
+                    ">=" => number >= limit,
+                    "<" => number < limit,
+                    "<=" => number <= limit,
+                    _ => true,
+                },
+                Err(_) => true,
+            },
+            _ => true,
+        }
+    };
+
+    if in_range {
+        Ok(())
+    } else {
+        Err(format!("fuera de rango ({})", domain))
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn option(kind: &str, domain: Value) -> OptionSchema {
+        OptionSchema { name: "x".to_string(), kind: kind.to_string(), value: Value::Null, domain, help: String::new() }
+    }
+
+    #[test]
+    fn check_domain_range() {
+        let domain = json!("float: 0 <= x <= 10");
+        assert!(check_domain(&domain, 0.0).is_ok());
+        assert!(check_domain(&domain, 10.0).is_ok());
+        assert!(check_domain(&domain, -0.1).is_err());
+        assert!(check_domain(&domain, 10.5).is_err());
+    }
+
+    #[test]
+    fn check_domain_named() {
+        assert!(check_domain(&json!("positive integer"), 0.0).is_ok());
+        assert!(check_domain(&json!("positive integer"), -1.0).is_err());
+        assert!(check_domain(&json!("negative"), 1.0).is_err());
+        assert!(check_domain(&json!("percent"), 100.0).is_ok());
+        assert!(check_domain(&json!("percent"), 101.0).is_err());
+    }
+
+    #[test]
+    fn check_domain_comparison() {
+        assert!(check_domain(&json!("float > 0.0"), 0.0).is_err());
+        assert!(check_domain(&json!("float > 0.0"), 0.1).is_ok());
+        assert!(check_domain(&json!("integer >= 1"), 1.0).is_ok());
+        assert!(check_domain(&json!("integer < 5"), 5.0).is_err());
+    }
+
+    #[test]
+    fn check_domain_unknown_accepts() {
+        assert!(check_domain(&json!("string"), 1e9).is_ok());
+        assert!(check_domain(&json!(["a", "b"]), -1.0).is_ok());
+        assert!(check_domain(&Value::Null, -1.0).is_ok());
+    }
+
+    #[test]
+    fn validate_int() {
+        let definition = option("int", json!("positive integer"));
+        assert!(validate_value(&definition, &json!(3)).is_ok());
+        assert!(validate_value(&definition, &json!("3")).is_ok());
+        assert!(validate_value(&definition, &json!(2.5)).is_err());
+        assert!(validate_value(&definition, &json!(-1)).is_err());
+        assert!(validate_value(&definition, &json!("tres")).is_err());
+    }
+
+    #[test]
+    fn validate_float() {
+        let definition = option("float", json!("float: 0 <= x <= 1"));
+        assert!(validate_value(&definition, &json!(0.5)).is_ok());
+        assert!(validate_value(&definition, &json!("0.25")).is_ok());
+        assert!(validate_value(&definition, &json!(1.5)).is_err());
+        assert!(validate_value(&definition, &json!(true)).is_err());
+    }
+
+    #[test]
+    fn validate_bool() {
+        let definition = option("bool", json!("bool"));
+        assert!(validate_value(&definition, &json!(true)).is_ok());
+        assert!(validate_value(&definition, &json!("false")).is_ok());
+        assert!(validate_value(&definition, &json!("yes")).is_err());
+        assert!(validate_value(&definition, &json!(1)).is_err());
+    }
+
+    #[test]
+    fn validate_enum() {
+        let definition = option("enum", json!(["ultra", "high", "medium"]));
+        assert!(validate_value(&definition, &json!("high")).is_ok());
+        assert!(validate_value(&definition, &json!("extreme")).is_err());
+        let numeric = option("enum", json!([1, 2]));
+        assert!(validate_value(&numeric, &json!("2")).is_ok());
+    }
+
+    #[test]
+    fn validate_unknown_kind_accepts() {
+        let definition = option("string", json!("string"));
+        assert!(validate_value(&definition, &json!("lo que sea")).is_ok());
+    }
+
+    #[test]
+    fn validate_options_unknown_name() {
+        let schema = vec![option("bool", json!("bool"))];
+        let options = vec![TaskOption { name: "y".to_string(), value: json!(true) }];
+        assert!(validate_options(&schema, &options).unwrap_err().contains("desconocida"));
+    }
+}
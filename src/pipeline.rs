@@ -0,0 +1,655 @@
+use crate::console;
+use crate::images::{self, JobImage};
+use crate::container::{ContainerManager, ContainerMode};
+use crate::form::FormField;
+use crate::jobs::{Job, JobState, JobStore};
+use crate::nodes::{NodeLease, NodeRegistry};
+use crate::options::{self, OptionSchemaCache};
+use crate::queue::JobQueue;
+use crate::webhooks::Webhooks;
+use bytes::Bytes;
+use futures::stream::{self, Stream};
+use reqwest::multipart::Part;
+use reqwest::Body;
+use std::collections::HashMap;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::sync::watch;
+use webodm_client::nodeodm::{NewTaskRequest, NodeOdmClient, NodeOdmError, TaskOption, TaskStatus};
+
+// Tamaño de los bloques con que se leen las imágenes y los resultados del disco.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Número de líneas de consola que se guardan cuando una tarea falla.
+const FAILED_OUTPUT_LINES: i64 = 20;
+
+// Motivo por el que una reconstrucción no terminó.
+enum Failure {
+    // Se perdió la comunicación con el nodo. Antes del commit el trabajo puede
+    // reintentarse en otro nodo; después, la tarea quedó en el nodo perdido.
+    NodeLost { committed: bool, message: String },
+    // Se pidió cancelar el trabajo.
+    Canceled,
+    Error(String),
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure::Error(message)
+    }
+}
+
+// Los errores de red y de disponibilidad indican que el nodo cayó; el resto son
+// errores de la tarea en sí.
+fn node_failure(committed: bool) -> impl Fn(NodeOdmError) -> Failure {
+    move |err| match err {
+        NodeOdmError::Http(_) | NodeOdmError::NotReady(..) => Failure::NodeLost { committed, message: err.to_string() },
+        other => Failure::Error(other.to_string()),
+    }
+}
+
+// Pedidos de cancelación de los trabajos que tienen una tarea de fondo en curso.
+#[derive(Clone, Default)]
+pub struct Cancellations {
+    senders: Arc<Mutex<HashMap<String, watch::Sender<bool>>>>,
+}
+
+impl Cancellations {
+    fn register(&self, job_id: &str) -> CancelSignal {
+        let (sender, receiver) = watch::channel(false);
+        self.senders.lock().unwrap().insert(job_id.to_string(), sender);
+        CancelSignal { receiver, job_id: job_id.to_string(), cancellations: self.clone() }
+    }
+
+    // Devuelve `false` si el trabajo no tiene una tarea de fondo en curso.
+    fn cancel(&self, job_id: &str) -> bool {
+        match self.senders.lock().unwrap().get(job_id) {
+            Some(sender) => {
+                sender.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Aviso de cancelación que recibe la tarea de fondo de un trabajo. Se da de baja al soltarlo.
+struct CancelSignal {
+    receiver: watch::Receiver<bool>,
+    job_id: String,
+    cancellations: Cancellations,
+}
+
+impl CancelSignal {
+    // Termina cuando se pide cancelar el trabajo. El emisor vive mientras exista la
+    // señal, así que no termina por otro motivo.
+    async fn wait(&mut self) {
+        let _ = self.receiver.wait_for(|canceled| *canceled).await;
+    }
+}
+
+impl Drop for CancelSignal {
+    fn drop(&mut self) {
+        // Un reinicio del trabajo pudo registrar ya su propia señal; solo se quita esta.
+        let mut senders = self.cancellations.senders.lock().unwrap();
+        if senders.get(&self.job_id).is_some_and(|s| s.subscribe().same_channel(&self.receiver)) {
+            senders.remove(&self.job_id);
+        }
+    }
+}
+
+// Estado compartido que necesitan las tareas de fondo de reconstrucción.
+#[derive(Clone)]
+pub struct Pipeline {
+    pub jobs: JobStore,
+    pub schema: OptionSchemaCache,
+    pub containers: ContainerManager,
+    pub nodes: NodeRegistry,
+    pub queue: JobQueue,
+    pub cancellations: Cancellations,
+    pub webhooks: Webhooks,
+    // Tiempo máximo de espera a que algún nodo responda.
+    pub node_ready_timeout: Duration,
+    // Directorio donde se conservan las imágenes de cada trabajo.
+    pub data_dir: PathBuf,
+    // Errores consecutivos al consultar una tarea antes de dar el nodo por perdido.
+    pub max_poll_failures: u32,
+}
+
+impl Pipeline {
+    // Directorio de datos de un trabajo: sus imágenes y los resultados descargados.
+    fn job_dir(&self, job_id: &str) -> PathBuf {
+        self.data_dir.join("jobs").join(job_id)
+    }
+
+    // Directorio con las imágenes recibidas para un trabajo.
+    pub fn images_dir(&self, job_id: &str) -> PathBuf {
+        self.job_dir(job_id).join("images")
+    }
+
+    // Log de la consola de ODM de un trabajo.
+    pub fn console_path(&self, job_id: &str) -> PathBuf {
+        self.job_dir(job_id).join("console.log")
+    }
+
+    // Directorio para recibir imágenes antes de que exista el trabajo.
+    pub fn uploads_dir(&self) -> PathBuf {
+        self.data_dir.join("uploads")
+    }
+
+    // Agrega a las imágenes del trabajo un archivo ya recibido en disco, con el
+    // nombre y tipo que mandó el cliente. Si el nombre ya existe se agrega `_2`,
+    // `_3`, ...; el enlace falla si el nombre está tomado, así que subidas en
+    // paralelo nunca se pisan.
+    pub fn add_image(
+        &self,
+        job_id: &str,
+        file: &Path,
+        filename: Option<&str>,
+        content_type: Option<&str>,
+    ) -> io::Result<JobImage> {
+        let images_dir = self.images_dir(job_id);
+        fs::create_dir_all(&images_dir)?;
+        let (stem, extension) = images::sanitize_filename(filename, content_type);
+        let candidates = std::iter::once(format!("{}.{}", stem, extension))
+            .chain((2..).map(|n| format!("{}_{}.{}", stem, n, extension)));
+        for name in candidates {
+            match fs::hard_link(file, images_dir.join(&name)) {
+                Ok(()) => {
+                    fs::remove_file(file)?;
+                    let content_type = images::resolve_content_type(content_type, &name);
+                    return Ok(JobImage { name, content_type });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!("los sufijos numéricos no se agotan")
+    }
+
+    // Imágenes en disco del trabajo con el tipo registrado al recibirlas, incluidos
+    // `gcp_list.txt` y `geo.txt`.
+    fn job_images(&self, job_id: &str) -> io::Result<Vec<JobImage>> {
+        let known = self.jobs.get(job_id).map(|job| job.images).unwrap_or_default();
+        let mut images: Vec<JobImage> = fs::read_dir(self.images_dir(job_id))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .map(|name| match known.iter().find(|image| image.name == name) {
+                Some(image) => image.clone(),
+                None => JobImage::from_name(name),
+            })
+            .collect();
+        images.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(images)
+    }
+
+    // Tarea de fondo: ejecuta la reconstrucción y registra el resultado en el trabajo.
+    // Si el nodo se pierde durante la subida se reintenta en otro nodo. Las imágenes
+    // se conservan hasta que se elimina el trabajo, para poder reenviarlo o reiniciarlo.
+    // La cancelación se registra al llamarla, antes de lanzar la tarea, así que un
+    // pedido de cancelar que llega antes de que la tarea arranque no se pierde.
+    pub fn run(self, job_id: String) -> impl Future<Output = ()> {
+        let canceled = self.cancellations.register(&job_id);
+        self.execute(job_id, canceled, false)
+    }
+
+    // Tarea de fondo para reiniciar un trabajo. Si su tarea sigue en el nodo se
+    // reinicia ahí con `/task/restart`; si no, se crea de nuevo desde las imágenes.
+    pub fn restart(self, job_id: String) -> impl Future<Output = ()> {
+        let canceled = self.cancellations.register(&job_id);
+        self.execute(job_id, canceled, true)
+    }
+
+    async fn execute(self, job_id: String, mut canceled: CancelSignal, reuse_task: bool) {
+        let jobs = &self.jobs;
+        let (task_options, priority) = match jobs.get(&job_id) {
+            Some(job) => (job.options, job.priority),
+            None => return,
+        };
+        // Esperar turno en la cola; el lugar se libera al terminar esta función.
+        let _slot = tokio::select! {
+            biased;
+            _ = canceled.wait() => return self.finish(&job_id, Err(Failure::Canceled)),
+            slot = self.queue.wait(&job_id, priority) => slot,
+        };
+        if reuse_task {
+            if let Some(result) = self.restart_task(&job_id, &task_options, &mut canceled).await {
+                return self.finish(&job_id, result);
+            }
+        }
+        let images = match self.job_images(&job_id) {
+            Ok(images) => images,
+            Err(err) => {
+                let err = format!("No se encontraron las imágenes del trabajo: {}", err);
+                return self.finish(&job_id, Err(Failure::Error(err)));
+            }
+        };
+
+        let mut lost_nodes: Vec<String> = Vec::new();
+        loop {
+            jobs.set_state(&job_id, JobState::StartingContainer);
+            let lease = match self.acquire_node(count_images(&images), &lost_nodes, &mut canceled).await {
+                Ok(lease) => lease,
+                Err(failure) => return self.finish(&job_id, Err(failure)),
+            };
+            jobs.set_node(&job_id, lease.node_name.clone(), lease.container_id.clone(), lease.host_port);
+
+            // Al cancelar se suelta la reconstrucción en curso, lo que corta la subida.
+            let result = tokio::select! {
+                biased;
+                _ = canceled.wait() => Err(Failure::Canceled),
+                result = self.reconstruct(&lease, &job_id, &images, task_options.clone()) => result,
+            };
+            if let Err(Failure::Canceled) = result {
+                self.cancel_task(&lease, &job_id).await;
+            }
+            // Liberar el nodo (en modo por trabajo se detiene el contenedor)
+            self.release_node(&lease).await;
+
+            match result {
+                Err(Failure::NodeLost { committed: false, message }) if lease.node_name.is_some() => {
+                    let node_name = lease.node_name.unwrap();
+                    self.nodes.record_failure(&node_name);
+                    println!("Se perdió el nodo {} durante la subida ({}); se reintenta en otro nodo.", node_name, message);
+                    lost_nodes.push(node_name);
+                }
+                result => return self.finish(&job_id, result),
+            }
+        }
+    }
+
+    // Reinicia la tarea que el trabajo dejó en su nodo con las opciones dadas y
+    // espera a que termine. Devuelve `None` si la tarea ya no está disponible
+    // (p. ej. se eliminó o era de un contenedor por trabajo).
+    async fn restart_task(
+        &self,
+        job_id: &str,
+        task_options: &[TaskOption],
+        canceled: &mut CancelSignal,
+    ) -> Option<Result<bool, Failure>> {
+        let job = self.jobs.get(job_id)?;
+        let uuid = job.task_uuid?;
+        let lease = self.nodes.attach(job.node.as_deref()?)?;
+        if let Err(err) = lease.node.restart(&uuid, Some(task_options)).await {
+            println!("No se pudo reiniciar la tarea {} ({}); se crea de nuevo.", uuid, err);
+            self.release_node(&lease).await;
+            return None;
+        }
+        println!("Tarea {} reiniciada en el nodo {}", uuid, job.node.as_deref().unwrap_or_default());
+        self.reset_console(job_id);
+        self.jobs.set_state(job_id, JobState::Committed);
+
+        let result = tokio::select! {
+            biased;
+            _ = canceled.wait() => Err(Failure::Canceled),
+            result = self.complete(&lease, job_id, &uuid) => result,
+        };
+        if let Err(Failure::Canceled) = result {
+            self.cancel_task(&lease, job_id).await;
+        }
+        self.release_node(&lease).await;
+        Some(result)
+    }
+
+    // Registra el resultado de la reconstrucción en el trabajo y envía los webhooks.
+    fn finish(&self, job_id: &str, result: Result<bool, Failure>) {
+        let jobs = &self.jobs;
+        match result {
+            Ok(true) => jobs.set_state(job_id, JobState::Completed),
+            Ok(false) | Err(Failure::Canceled) => {
+                println!("El trabajo {} fue cancelado", job_id);
+                jobs.set_state(job_id, JobState::Canceled);
+            }
+            Err(Failure::NodeLost { message, .. }) => {
+                println!("El trabajo {} perdió su nodo: {}", job_id, message);
+                jobs.set_error(job_id, message);
+                jobs.set_state(job_id, JobState::NodeLost);
+            }
+            Err(Failure::Error(err)) => {
+                println!("El trabajo {} ha fallado: {}", job_id, err);
+                jobs.fail(job_id, err);
+            }
+        }
+        self.webhooks.job_finished(job_id);
+    }
+
+    // Elimina el trabajo junto con sus imágenes y resultados. Si su tarea sigue en
+    // un nodo registrado (p. ej. tras fallar o perder el nodo) también se elimina
+    // de NodeODM.
+    pub async fn delete(&self, job_id: &str) -> io::Result<()> {
+        if let Some(job) = self.jobs.get(job_id) {
+            let node = job.node.as_deref().and_then(|name| self.nodes.get(name));
+            if let (Some(uuid), Some(node)) = (job.task_uuid, node) {
+                if let Err(err) = node.config.client().remove(&uuid).await {
+                    println!("Error al eliminar la tarea {}: {}", uuid, err);
+                }
+            }
+        }
+        match fs::remove_dir_all(self.job_dir(job_id)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        self.jobs.remove(job_id);
+        Ok(())
+    }
+
+    // Pide cancelar un trabajo en curso. Devuelve `false` si no tiene una tarea de
+    // fondo (p. ej. en `node_lost`).
+    pub fn cancel(&self, job_id: &str) -> bool {
+        self.cancellations.cancel(job_id)
+    }
+
+    // Cancela y elimina la tarea del trabajo en NodeODM. Antes del commit la tarea
+    // todavía no se procesa, así que basta con eliminarla.
+    async fn cancel_task(&self, lease: &NodeLease, job_id: &str) {
+        let job = match self.jobs.get(job_id) {
+            Some(job) => job,
+            None => return,
+        };
+        let uuid = match job.task_uuid {
+            Some(uuid) => uuid,
+            None => return,
+        };
+        if job.state.is_committed() {
+            if let Err(err) = lease.node.cancel(&uuid).await {
+                println!("Error al cancelar la tarea {}: {}", uuid, err);
+            }
+        }
+        if let Err(err) = lease.node.remove(&uuid).await {
+            println!("Error al eliminar la tarea {}: {}", uuid, err);
+        }
+    }
+
+    // Recupera los trabajos que quedaron en curso al detenerse el servicio. Los que
+    // ya tenían la tarea en NodeODM se vuelven a enlazar con ella; los demás se
+    // reinician desde sus imágenes. Los contenedores por trabajo que no pertenecen
+    // a un trabajo activo se detienen.
+    pub async fn recover(&self) {
+        for job in self.jobs.list() {
+            let reattach = job.state.is_committed() && job.task_uuid.is_some();
+            if let Some(container_id) = &job.container_id {
+                if !reattach || job.host_port.is_none() {
+                    self.containers.stop_orphan(container_id).await;
+                }
+            }
+            // Los trabajos que esperan imágenes siguen esperándolas.
+            if job.state.is_terminal() || matches!(job.state, JobState::Receiving { .. }) {
+                continue;
+            }
+
+            if reattach {
+                println!("Recuperando el trabajo {} (tarea {})", job.id, job.task_uuid.as_deref().unwrap_or_default());
+                tokio::spawn(self.clone().resume(job));
+            } else {
+                println!("Reiniciando el trabajo {}", job.id);
+                self.jobs.set_state(&job.id, JobState::Queued);
+                tokio::spawn(self.clone().run(job.id));
+            }
+        }
+    }
+
+    // Tarea de fondo para un trabajo recuperado: consulta su tarea en NodeODM y
+    // continúa esperando o descargando desde donde se quedó.
+    async fn resume(self, job: Job) {
+        let _slot = self.queue.occupy();
+        let mut canceled = self.cancellations.register(&job.id);
+        let uuid = job.task_uuid.unwrap_or_default();
+        let lease = match (&job.node, &job.container_id, job.host_port) {
+            (Some(name), _, _) => self.nodes.attach(name),
+            (None, Some(container_id), Some(host_port)) => {
+                Some(self.containers.attach_job_container(container_id.clone(), host_port))
+            }
+            _ => None,
+        };
+        let lease = match lease {
+            Some(lease) => lease,
+            None => {
+                let message = format!("El nodo {} ya no está registrado", job.node.unwrap_or_default());
+                return self.finish(&job.id, Err(Failure::NodeLost { committed: true, message }));
+            }
+        };
+
+        let result = match lease.node.task_info(&uuid).await {
+            Ok(_) => tokio::select! {
+                biased;
+                _ = canceled.wait() => Err(Failure::Canceled),
+                result = self.complete(&lease, &job.id, &uuid) => result,
+            },
+            Err(NodeOdmError::Api(message)) => {
+                Err(Failure::Error(format!("La tarea {} ya no existe en NodeODM: {}", uuid, message)))
+            }
+            Err(err) => Err(node_failure(true)(err)),
+        };
+        if let Err(Failure::Canceled) = result {
+            self.cancel_task(&lease, &job.id).await;
+        }
+        self.release_node(&lease).await;
+        self.finish(&job.id, result);
+    }
+
+    // En modo por trabajo se crea un contenedor propio; si no, el planificador
+    // elige el nodo registrado menos cargado. La espera del planificador se corta
+    // al cancelar; la creación del contenedor no, para no dejarlo huérfano.
+    async fn acquire_node(
+        &self,
+        image_count: usize,
+        exclude: &[String],
+        canceled: &mut CancelSignal,
+    ) -> Result<NodeLease, Failure> {
+        match self.containers.mode() {
+            ContainerMode::PerJob => Ok(self.containers.start_job_container().await?),
+            ContainerMode::Shared | ContainerMode::None => tokio::select! {
+                lease = self.nodes.acquire(image_count, exclude, self.node_ready_timeout) => Ok(lease?),
+                _ = canceled.wait() => Err(Failure::Canceled),
+            },
+        }
+    }
+
+    async fn release_node(&self, lease: &NodeLease) {
+        if let Some(name) = &lease.node_name {
+            self.nodes.release(name);
+        }
+        self.containers.release(lease).await;
+    }
+
+    // Sube las imágenes a NodeODM, espera a que termine la tarea y descarga el resultado.
+    // Devuelve `false` si la tarea fue cancelada en NodeODM.
+    async fn reconstruct(
+        &self,
+        lease: &NodeLease,
+        job_id: &str,
+        images: &[JobImage],
+        task_options: Vec<TaskOption>,
+    ) -> Result<bool, Failure> {
+        let jobs = &self.jobs;
+        let node = &lease.node;
+
+        // Validar las opciones contra el esquema del nodo antes de crear la tarea.
+        let node_schema = node.options().await.map_err(node_failure(false))?;
+        self.schema.set(node_schema.clone());
+        options::validate_options(&node_schema, &task_options)?;
+
+        // 1. Initialize a new task
+        let request = NewTaskRequest { options: task_options, ..Default::default() };
+        let task = node.init_task(&request).await.map_err(node_failure(false))?;
+        let uuid = task.uuid;
+        jobs.set_task_uuid(job_id, Some(uuid.clone()));
+        self.reset_console(job_id);
+
+        // 2. Upload the images
+        let total = count_images(images);
+        let mut uploaded = 0;
+        jobs.set_state(job_id, JobState::Uploading { uploaded, total });
+        for image in images {
+            let path = self.images_dir(job_id).join(&image.name);
+            let part = file_part(&path).await.map_err(|e| format!("Error al leer {}: {}", path.display(), e))?;
+            let part = part
+                .file_name(image.name.clone())
+                .mime_str(&image.content_type)
+                .map_err(|e| format!("Tipo de contenido inválido en {}: {}", image.name, e))?;
+            node.upload(&uuid, part).await.map_err(node_failure(false))?;
+            println!("Uploaded {}", image.name);
+            if FormField::for_file_name(&image.name) == FormField::Image {
+                uploaded += 1;
+                jobs.set_state(job_id, JobState::Uploading { uploaded, total });
+            }
+        }
+
+        // 3. Commit the task
+        node.commit(&uuid).await.map_err(node_failure(false))?;
+        jobs.set_state(job_id, JobState::Committed);
+
+        self.complete(lease, job_id, &uuid).await
+    }
+
+    // Espera a que termine una tarea ya enviada, descarga el resultado y la elimina
+    // del nodo. Devuelve `false` si la tarea fue cancelada en NodeODM.
+    async fn complete(&self, lease: &NodeLease, job_id: &str, uuid: &str) -> Result<bool, Failure> {
+        let jobs = &self.jobs;
+        let node = &lease.node;
+
+        // 4. Verificar si la tarea ha terminado.
+        if !self.wait_for_task(lease, job_id, uuid).await? {
+            return Ok(false);
+        }
+
+        // 5. Descargar el archivo all.zip
+        jobs.set_state(job_id, JobState::Downloading);
+        let download_path = self.job_dir(job_id).join("all.zip");
+        node.download(uuid, "all.zip", &download_path).await.map_err(node_failure(true))?;
+        jobs.add_artifact(job_id, download_path.display().to_string());
+
+        println!("Archivo {} descargado con éxito!", download_path.display());
+
+        // 6. Eliminar la tarea
+        node.remove(uuid).await.map_err(node_failure(true))?;
+
+        Ok(true)
+    }
+
+    // Empieza un log vacío para una tarea nueva o reiniciada.
+    fn reset_console(&self, job_id: &str) {
+        if let Err(err) = console::reset(&self.console_path(job_id)) {
+            println!("Error al crear el log del trabajo {}: {}", job_id, err);
+        }
+    }
+
+    // Agrega al log las líneas de consola nuevas desde `offset`. Un error aquí no
+    // interrumpe el trabajo; las líneas se piden de nuevo en la siguiente consulta.
+    async fn sync_console(&self, node: &NodeOdmClient, job_id: &str, uuid: &str, offset: &mut usize) {
+        let lines = match node.output(uuid, *offset as i64).await {
+            Ok(lines) => lines,
+            Err(err) => {
+                println!("Error al obtener la consola de la tarea {}: {}", uuid, err);
+                return;
+            }
+        };
+        if lines.is_empty() {
+            return;
+        }
+        match console::append(&self.console_path(job_id), &lines) {
+            Ok(()) => *offset += lines.len(),
+            Err(err) => println!("Error al guardar el log del trabajo {}: {}", job_id, err),
+        }
+    }
+
+    // Consulta la tarea hasta que termine. Devuelve `false` si fue cancelada. Tras
+    // `max_poll_failures` errores de conexión consecutivos de este trabajo el nodo
+    // se da por perdido. Los errores también cuentan para el disyuntor del nodo,
+    // pero ese solo decide dónde se ubican los trabajos nuevos: lo comparten todos
+    // los trabajos del nodo y no alcanza para abandonar una tarea en curso.
+    async fn wait_for_task(&self, lease: &NodeLease, job_id: &str, uuid: &str) -> Result<bool, Failure> {
+        let jobs = &self.jobs;
+        let node = &lease.node;
+        let mut failures = 0;
+        // Líneas de consola ya guardadas; al recuperar un trabajo se sigue desde ahí.
+        let mut console_lines = console::read(&self.console_path(job_id)).map_or(0, |lines| lines.len());
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(10)).await;  // Espera antes de verificar nuevamente.
+            let task_info = match node.task_info(uuid).await {
+                Ok(info) => info,
+                Err(NodeOdmError::Http(err)) => {
+                    println!("Error al obtener información de la tarea: {}", err);
+                    failures += 1;
+                    if let Some(name) = &lease.node_name {
+                        self.nodes.record_failure(name);
+                    }
+                    if failures >= self.max_poll_failures {
+                        return Err(Failure::NodeLost { committed: true, message: err.to_string() });
+                    }
+                    continue; // Volver al principio del bucle para intentarlo nuevamente
+                }
+                Err(err) => return Err(Failure::Error(err.to_string())),
+            };
+            failures = 0;
+            if let Some(name) = &lease.node_name {
+                self.nodes.record_success(name);
+            }
+            self.sync_console(node, job_id, uuid, &mut console_lines).await;
+
+            match task_info.status.code {
+                TaskStatus::Queued => {
+                    println!("La tarea está en cola en NodeODM.");
+                },
+                TaskStatus::Running => {
+                    println!("La tarea sigue en desarrollo.");
+                    jobs.set_state(job_id, JobState::Running { progress: task_info.progress });
+                },
+                TaskStatus::Completed => {
+                    println!("La tarea ha sido completada con éxito.");
+                    return Ok(true);
+                },
+                TaskStatus::Canceled => {
+                    println!("La tarea ha sido cancelada.");
+                    return Ok(false);
+                },
+                TaskStatus::Failed => {
+                    // Conservar las últimas líneas de la consola para diagnosticar el fallo.
+                    let output = node.output(uuid, -FAILED_OUTPUT_LINES).await.unwrap_or_default();
+                    jobs.set_output_tail(job_id, output);
+                    let message = task_info.status.error_message.unwrap_or_else(|| "sin mensaje de error".to_string());
+                    return Err(Failure::Error(format!("La tarea ha fallado en NodeODM: {}", message)));
+                },
+                TaskStatus::Unknown(code) => {
+                    return Err(Failure::Error(format!("La tarea ha finalizado con un estado desconocido ({})", code)));
+                }
+            }
+        }
+    }
+}
+
+// Lee un archivo por bloques, para enviarlo sin cargarlo completo en memoria.
+pub fn file_chunks(file: tokio::fs::File) -> impl Stream<Item = io::Result<Bytes>> {
+    stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        match file.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(read) => {
+                buffer.truncate(read);
+                Some((Ok(Bytes::from(buffer)), Some(file)))
+            }
+            Err(err) => Some((Err(err), None)),
+        }
+    })
+}
+
+// Imágenes de la lista, sin contar `gcp_list.txt` ni `geo.txt`.
+fn count_images(images: &[JobImage]) -> usize {
+    images.iter().filter(|image| FormField::for_file_name(&image.name) == FormField::Image).count()
+}
+
+// Parte multipart que envía la imagen desde el disco por bloques.
+async fn file_part(path: &Path) -> io::Result<Part> {
+    let file = tokio::fs::File::open(path).await?;
+    let length = file.metadata().await?.len();
+    Ok(Part::stream_with_length(Body::wrap_stream(file_chunks(file)), length))
+}
@@ -0,0 +1,131 @@
+use crate::nodes::NodeRegistry;
+use crate::options::{self, OptionSchemaCache};
+use crate::settings::{self, SettingsStore};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use webodm_client::nodeodm::TaskOption;
+
+// Conjunto de opciones de ODM con nombre, p. ej. "fast-ortho".
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub options: Vec<TaskOption>,
+}
+
+// Registro de presets disponible para todos los handlers.
+#[derive(Clone, Default)]
+pub struct PresetStore {
+    presets: Arc<RwLock<BTreeMap<String, Preset>>>,
+}
+
+impl PresetStore {
+    // Presets de la configuración con los cambios hechos por la API encima.
+    pub fn new(presets: Vec<Preset>, settings: &SettingsStore) -> Self {
+        let mut presets: BTreeMap<String, Preset> = presets.into_iter().map(|p| (p.name.clone(), p)).collect();
+        for (name, body) in settings.load::<PresetBody>(settings::PRESETS) {
+            match body {
+                Some(body) => {
+                    let preset = Preset { name: name.clone(), description: body.description, options: body.options };
+                    presets.insert(name, preset);
+                }
+                None => {
+                    presets.remove(&name);
+                }
+            }
+        }
+        PresetStore { presets: Arc::new(RwLock::new(presets)) }
+    }
+
+    pub fn list(&self) -> Vec<Preset> {
+        self.presets.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Preset> {
+        self.presets.read().unwrap().get(name).cloned()
+    }
+
+    // Inserta o reemplaza un preset. Devuelve `true` si ya existía.
+    pub fn put(&self, preset: Preset) -> bool {
+        self.presets.write().unwrap().insert(preset.name.clone(), preset).is_some()
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.presets.write().unwrap().remove(name).is_some()
+    }
+
+    // Combina las opciones del preset con las de la petición; las de la petición
+    // tienen prioridad cuando el nombre coincide.
+    pub fn resolve(&self, preset: Option<&str>, overrides: Vec<TaskOption>) -> Result<Vec<TaskOption>, String> {
+        let base = match preset {
+            Some(name) => self.get(name).ok_or_else(|| format!("Preset desconocido: {}", name))?.options,
+            None => Vec::new(),
+        };
+        Ok(options::merge_options(base, overrides))
+    }
+}
+
+// Cuerpo de `PUT /presets/{name}`; también es lo que se guarda de cada preset.
+#[derive(Deserialize, Serialize)]
+pub struct PresetBody {
+    #[serde(default)]
+    pub description: String,
+    pub options: Vec<TaskOption>,
+}
+
+// GET /presets
+pub async fn list_presets(presets: web::Data<PresetStore>) -> HttpResponse {
+    HttpResponse::Ok().json(presets.list())
+}
+
+// GET /presets/{name}
+pub async fn get_preset(presets: web::Data<PresetStore>, path: web::Path<String>) -> HttpResponse {
+    match presets.get(&path.into_inner()) {
+        Some(preset) => HttpResponse::Ok().json(preset),
+        None => HttpResponse::NotFound().body("Preset no encontrado"),
+    }
+}
+
+// PUT /presets/{name} — crea o reemplaza el preset.
+pub async fn put_preset(
+    presets: web::Data<PresetStore>,
+    schema: web::Data<OptionSchemaCache>,
+    nodes: web::Data<NodeRegistry>,
+    settings: web::Data<SettingsStore>,
+    path: web::Path<String>,
+    body: web::Json<PresetBody>,
+) -> HttpResponse {
+    let body = body.into_inner();
+    let name = path.into_inner();
+    if let Some(schema) = schema.load(&nodes).await {
+        if let Err(err) = options::validate_options(&schema, &body.options) {
+            return HttpResponse::BadRequest().body(err);
+        }
+    }
+
+    settings.save(settings::PRESETS, &name, &body);
+    let preset = Preset { name, description: body.description, options: body.options };
+    if presets.put(preset.clone()) {
+        HttpResponse::Ok().json(preset)
+    } else {
+        HttpResponse::Created().json(preset)
+    }
+}
+
+// DELETE /presets/{name}
+pub async fn delete_preset(
+    presets: web::Data<PresetStore>,
+    settings: web::Data<SettingsStore>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    let name = path.into_inner();
+    if presets.remove(&name) {
+        settings.delete(settings::PRESETS, &name);
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().body("Preset no encontrado")
+    }
+}
@@ -0,0 +1,208 @@
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+// Duraciones recientes que se promedian para estimar el tiempo de espera.
+const RECENT_DURATIONS: usize = 20;
+
+// Espera sugerida en `Retry-After` mientras no hay trabajos terminados para estimarla.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    // Reconstrucciones que se procesan a la vez en todo el servicio.
+    pub max_concurrent: usize,
+    // Trabajos que pueden esperar en la cola; al llenarse se responde 429.
+    pub max_queued: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig { max_concurrent: 2, max_queued: 50 }
+    }
+}
+
+// Prioridad de un trabajo en la cola. Dentro de la misma prioridad se respeta
+// el orden de llegada.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+struct Waiting {
+    job_id: String,
+    priority: Priority,
+}
+
+#[derive(Default)]
+struct QueueState {
+    // Trabajos en espera, ordenados por prioridad y llegada.
+    waiting: Vec<Waiting>,
+    running: usize,
+    durations: VecDeque<Duration>,
+}
+
+impl QueueState {
+    fn average_duration(&self) -> Option<Duration> {
+        if self.durations.is_empty() {
+            return None;
+        }
+        Some(self.durations.iter().sum::<Duration>() / self.durations.len() as u32)
+    }
+}
+
+// Posición de un trabajo en `GET /queue`.
+#[derive(Serialize)]
+pub struct QueueEntry {
+    pub job_id: String,
+    pub priority: Priority,
+    // Empieza en 1.
+    pub position: usize,
+    // Segundos estimados hasta que empiece a procesarse.
+    pub eta_secs: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct QueueSnapshot {
+    pub running: usize,
+    pub max_concurrent: usize,
+    pub max_queued: usize,
+    pub queued: Vec<QueueEntry>,
+}
+
+// Cola de trabajos con un límite global de reconstrucciones simultáneas. El
+// límite por nodo lo aplica el planificador con `max_tasks`.
+#[derive(Clone)]
+pub struct JobQueue {
+    config: QueueConfig,
+    state: Arc<Mutex<QueueState>>,
+    notify: Arc<Notify>,
+}
+
+impl JobQueue {
+    pub fn new(config: QueueConfig) -> Self {
+        JobQueue { config, state: Arc::default(), notify: Arc::default() }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.state.lock().unwrap().waiting.len() >= self.config.max_queued
+    }
+
+    // Tiempo sugerido al cliente para reintentar cuando la cola está llena.
+    pub fn retry_after(&self) -> Duration {
+        self.state.lock().unwrap().average_duration().unwrap_or(DEFAULT_RETRY_AFTER)
+    }
+
+    // Espera el turno del trabajo. El lugar se libera al soltar el `QueueSlot`. Si
+    // se deja de esperar (p. ej. al cancelar el trabajo) sale de la cola.
+    pub async fn wait(&self, job_id: &str, priority: Priority) -> QueueSlot {
+        {
+            let mut state = self.state.lock().unwrap();
+            let index = state.waiting.partition_point(|w| w.priority <= priority);
+            state.waiting.insert(index, Waiting { job_id: job_id.to_string(), priority });
+        }
+        let _waiting = WaitingGuard { queue: self, job_id };
+        loop {
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                let first = state.waiting.first().is_some_and(|w| w.job_id == job_id);
+                if first && state.running < self.config.max_concurrent {
+                    state.waiting.remove(0);
+                    return self.occupy_locked(&mut state);
+                }
+            }
+            notified.await;
+        }
+    }
+
+    // Ocupa un lugar sin esperar, para trabajos recuperados que ya están en un nodo.
+    pub fn occupy(&self) -> QueueSlot {
+        let mut state = self.state.lock().unwrap();
+        self.occupy_locked(&mut state)
+    }
+
+    fn occupy_locked(&self, state: &mut QueueState) -> QueueSlot {
+        state.running += 1;
+        // Puede haber otro lugar libre para el siguiente trabajo.
+        self.notify.notify_waiters();
+        QueueSlot { queue: self.clone(), started: Instant::now() }
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        let state = self.state.lock().unwrap();
+        let average = state.average_duration();
+        let max_concurrent = self.config.max_concurrent.max(1);
+        let queued = state
+            .waiting
+            .iter()
+            .enumerate()
+            .map(|(index, w)| {
+                // Tandas de `max_concurrent` trabajos que deben terminar antes que este.
+                let free = max_concurrent.saturating_sub(state.running);
+                let rounds = if index < free { 0 } else { (index - free) / max_concurrent + 1 };
+                QueueEntry {
+                    job_id: w.job_id.clone(),
+                    priority: w.priority,
+                    position: index + 1,
+                    eta_secs: average.map(|avg| avg.as_secs() * rounds as u64),
+                }
+            })
+            .collect();
+        QueueSnapshot {
+            running: state.running,
+            max_concurrent: self.config.max_concurrent,
+            max_queued: self.config.max_queued,
+            queued,
+        }
+    }
+}
+
+// Quita el trabajo de la lista de espera si `wait` termina sin obtener lugar.
+struct WaitingGuard<'a> {
+    queue: &'a JobQueue,
+    job_id: &'a str,
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().unwrap();
+        let before = state.waiting.len();
+        state.waiting.retain(|w| w.job_id != self.job_id);
+        if state.waiting.len() != before {
+            self.queue.notify.notify_waiters();
+        }
+    }
+}
+
+// Lugar ocupado por un trabajo en ejecución. Al soltarlo se registra la duración
+// y se despierta al siguiente de la cola.
+pub struct QueueSlot {
+    queue: JobQueue,
+    started: Instant,
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().unwrap();
+        state.running = state.running.saturating_sub(1);
+        if state.durations.len() == RECENT_DURATIONS {
+            state.durations.pop_front();
+        }
+        state.durations.push_back(self.started.elapsed());
+        self.queue.notify.notify_waiters();
+    }
+}
+
+// GET /queue
+pub async fn get_queue(queue: web::Data<JobQueue>) -> HttpResponse {
+    HttpResponse::Ok().json(queue.snapshot())
+}
@@ -0,0 +1,224 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::fmt;
+use std::io;
+use tokio::process::Command;
+
+// ID que devuelve el backend externo en lugar de un contenedor real.
+pub const EXTERNAL_ID: &str = "external";
+
+// Errores de los backends de contenedores.
+#[derive(Debug)]
+pub enum RuntimeError {
+    // No se pudo ejecutar el binario (p. ej. docker no está instalado).
+    Spawn { program: String, source: io::Error },
+    // El comando terminó con código distinto de cero.
+    Failed { command: String, code: Option<i32>, message: String },
+    // El comando terminó bien pero su salida no es la esperada.
+    Output { command: String, output: String },
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Spawn { program, source } => write!(f, "no se pudo ejecutar {}: {}", program, source),
+            RuntimeError::Failed { command, code: Some(code), message } => {
+                write!(f, "`{}` terminó con código {}: {}", command, code, message)
+            }
+            RuntimeError::Failed { command, code: None, message } => {
+                write!(f, "`{}` fue interrumpido: {}", command, message)
+            }
+            RuntimeError::Output { command, output } => {
+                write!(f, "salida inesperada de `{}`: {:?}", command, output)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+// Parámetros para crear un contenedor de NodeODM.
+pub struct RunSpec<'a> {
+    pub image: &'a str,
+    pub name: Option<&'a str>,
+    pub host_port: u16,
+    // Borrar el contenedor al detenerlo, con sus imágenes y resultados (`--rm`).
+    pub remove_on_stop: bool,
+}
+
+// Operaciones sobre contenedores que necesita el administrador de NodeODM.
+#[async_trait]
+pub trait ContainerRuntime: Send + Sync {
+    // Busca un contenedor (en ejecución o detenido) por nombre.
+    async fn find(&self, name: &str) -> Result<Option<String>, RuntimeError>;
+    async fn is_running(&self, id: &str) -> Result<bool, RuntimeError>;
+    // Crea e inicia un contenedor y devuelve su ID.
+    async fn run(&self, spec: &RunSpec<'_>) -> Result<String, RuntimeError>;
+    async fn start(&self, id: &str) -> Result<(), RuntimeError>;
+    async fn restart(&self, id: &str) -> Result<(), RuntimeError>;
+    async fn stop(&self, id: &str) -> Result<(), RuntimeError>;
+    // URL base del NodeODM publicado en `host_port`.
+    fn node_url(&self, host_port: u16) -> String;
+}
+
+// Backend que se configura en `container.runtime`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeKind {
+    Docker,
+    Podman,
+    External,
+}
+
+// Crea el backend indicado. `external_url` solo se usa con `RuntimeKind::External`.
+pub fn from_config(kind: &RuntimeKind, external_url: &str) -> Box<dyn ContainerRuntime> {
+    match kind {
+        RuntimeKind::Docker => Box::new(CliRuntime::docker()),
+        RuntimeKind::Podman => Box::new(CliRuntime::podman()),
+        RuntimeKind::External => Box::new(ExternalRuntime { url: external_url.trim_end_matches('/').to_string() }),
+    }
+}
+
+// Backend para CLIs compatibles con la de Docker (docker, podman).
+pub struct CliRuntime {
+    program: &'static str,
+}
+
+impl CliRuntime {
+    pub fn docker() -> Self {
+        CliRuntime { program: "docker" }
+    }
+
+    pub fn podman() -> Self {
+        CliRuntime { program: "podman" }
+    }
+
+    // Ejecuta el comando y devuelve su salida estándar; un código de salida distinto
+    // de cero se convierte en error con el mensaje de stderr.
+    async fn exec(&self, args: &[&str]) -> Result<String, RuntimeError> {
+        let command = format!("{} {}", self.program, args.join(" "));
+        let output = Command::new(self.program)
+            .args(args)
+            .output()
+            .await
+            .map_err(|source| RuntimeError::Spawn { program: self.program.to_string(), source })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(RuntimeError::Failed { command, code: output.status.code(), message: error_message(&stderr) });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+// Extrae el mensaje útil de stderr, p. ej. de
+// "docker: Error response from daemon: ... port is already allocated."
+fn error_message(stderr: &str) -> String {
+    let line = stderr
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty() && !line.starts_with("See '"))
+        .unwrap_or("sin mensaje de error");
+    match line.find("Error response from daemon: ") {
+        Some(index) => line[index + "Error response from daemon: ".len()..].to_string(),
+        None => line.trim_start_matches("Error: ").to_string(),
+    }
+}
+
+#[async_trait]
+impl ContainerRuntime for CliRuntime {
+    async fn find(&self, name: &str) -> Result<Option<String>, RuntimeError> {
+        // Sin `--type container`, `inspect` también encuentra imágenes, redes o
+        // volúmenes con ese nombre.
+        match self.exec(&["inspect", "--type", "container", "--format", "{{.Id}}", name]).await {
+            Ok(id) if !id.is_empty() => Ok(Some(id)),
+            Ok(_) => Ok(None),
+            Err(RuntimeError::Failed { message, .. }) if message.to_lowercase().contains("no such") => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn is_running(&self, id: &str) -> Result<bool, RuntimeError> {
+        let args = ["inspect", "--type", "container", "--format", "{{.State.Running}}", id];
+        match self.exec(&args).await?.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => Err(RuntimeError::Output {
+                command: format!("{} {}", self.program, args.join(" ")),
+                output: other.to_string(),
+            }),
+        }
+    }
+
+    async fn run(&self, spec: &RunSpec<'_>) -> Result<String, RuntimeError> {
+        let port_mapping = format!("{}:3000", spec.host_port);
+        let mut args = vec!["run", "-d", "-p", &port_mapping];
+        if let Some(name) = spec.name {
+            args.extend(["--name", name]);
+        }
+        if spec.remove_on_stop {
+            args.push("--rm");
+        }
+        args.push(spec.image);
+
+        let id = self.exec(&args).await?;
+        // `run -d` imprime solo el ID; si hubo que descargar la imagen puede haber más líneas.
+        match id.lines().next_back() {
+            Some(id) if !id.trim().is_empty() => Ok(id.trim().to_string()),
+            _ => Err(RuntimeError::Output { command: format!("{} {}", self.program, args.join(" ")), output: id }),
+        }
+    }
+
+    async fn start(&self, id: &str) -> Result<(), RuntimeError> {
+        self.exec(&["start", id]).await.map(|_| ())
+    }
+
+    async fn restart(&self, id: &str) -> Result<(), RuntimeError> {
+        self.exec(&["restart", id]).await.map(|_| ())
+    }
+
+    async fn stop(&self, id: &str) -> Result<(), RuntimeError> {
+        self.exec(&["stop", id]).await.map(|_| ())
+    }
+
+    fn node_url(&self, host_port: u16) -> String {
+        format!("http://localhost:{}", host_port)
+    }
+}
+
+// Backend para un NodeODM que ya corre fuera del servicio (p. ej. en compose):
+// no crea ni detiene contenedores, solo apunta a la URL configurada.
+pub struct ExternalRuntime {
+    url: String,
+}
+
+#[async_trait]
+impl ContainerRuntime for ExternalRuntime {
+    async fn find(&self, _name: &str) -> Result<Option<String>, RuntimeError> {
+        Ok(Some(EXTERNAL_ID.to_string()))
+    }
+
+    async fn is_running(&self, _id: &str) -> Result<bool, RuntimeError> {
+        Ok(true)
+    }
+
+    async fn run(&self, _spec: &RunSpec<'_>) -> Result<String, RuntimeError> {
+        Ok(EXTERNAL_ID.to_string())
+    }
+
+    async fn start(&self, _id: &str) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    async fn restart(&self, _id: &str) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    async fn stop(&self, _id: &str) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+
+    fn node_url(&self, _host_port: u16) -> String {
+        self.url.clone()
+    }
+}
@@ -0,0 +1,85 @@
+use rusqlite::{params, Connection};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS settings (
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    data TEXT,
+    PRIMARY KEY (kind, name)
+);
+";
+
+// Clases de ajustes que se administran por la API.
+pub const PRESETS: &str = "preset";
+pub const NODES: &str = "node";
+
+// Cambios hechos por la API a presets y nodos, guardados en la misma base que los
+// trabajos. La configuración es la base y al iniciar se le aplican estos cambios.
+// Una fila sin `data` es un elemento eliminado.
+#[derive(Clone)]
+pub struct SettingsStore {
+    db: Arc<Mutex<Connection>>,
+}
+
+impl SettingsStore {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let db = Connection::open(path)?;
+        db.execute_batch(SCHEMA)?;
+        Ok(SettingsStore { db: Arc::new(Mutex::new(db)) })
+    }
+
+    // Cambios guardados de una clase: `Some` para crear o reemplazar y `None` para eliminar.
+    pub fn load<T: DeserializeOwned>(&self, kind: &str) -> Vec<(String, Option<T>)> {
+        let db = self.db.lock().unwrap();
+        let result = db.prepare("SELECT name, data FROM settings WHERE kind = ?1").and_then(|mut statement| {
+            let rows = statement.query_map(params![kind], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+        });
+        let rows = match result {
+            Ok(rows) => rows,
+            Err(err) => {
+                println!("No se pudieron leer los ajustes de {}: {}", kind, err);
+                return Vec::new();
+            }
+        };
+        rows.into_iter()
+            .filter_map(|(name, data)| match data.map(|data| serde_json::from_str::<T>(&data)).transpose() {
+                Ok(value) => Some((name, value)),
+                Err(err) => {
+                    println!("No se pudo leer el ajuste guardado {} {}: {}", kind, name, err);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // Guarda un elemento creado o reemplazado.
+    pub fn save<T: Serialize>(&self, kind: &str, name: &str, value: &T) {
+        match serde_json::to_string(value) {
+            Ok(data) => self.write(kind, name, Some(data)),
+            Err(err) => println!("No se pudo guardar el ajuste {} {}: {}", kind, name, err),
+        }
+    }
+
+    // Registra que un elemento se eliminó, también si venía de la configuración.
+    pub fn delete(&self, kind: &str, name: &str) {
+        self.write(kind, name, None);
+    }
+
+    fn write(&self, kind: &str, name: &str, data: Option<String>) {
+        let result = self.db.lock().unwrap().execute(
+            "INSERT INTO settings (kind, name, data) VALUES (?1, ?2, ?3)
+             ON CONFLICT(kind, name) DO UPDATE SET data = excluded.data",
+            params![kind, name, data],
+        );
+        if let Err(err) = result {
+            println!("No se pudo guardar el ajuste {} {}: {}", kind, name, err);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::jobs::JobState;
+use crate::pipeline::Pipeline;
+use actix_web::http::header::HeaderMap;
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder};
+use base64::Engine;
+use futures_util::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+// Subidas reanudables con el protocolo tus 1.0 (https://tus.io/protocols/resumable-upload),
+// con la extensión `creation`. Cada subida es un archivo de un trabajo en `receiving`;
+// al completarse se mueve a las imágenes del trabajo.
+
+pub const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+// Datos de una subida en curso, guardados junto al archivo parcial para poder
+// reanudarla tras un reinicio del servicio.
+#[derive(Deserialize, Serialize)]
+struct UploadInfo {
+    job_id: String,
+    filename: String,
+    // Tipo de contenido declarado en `filetype`.
+    #[serde(default)]
+    filetype: Option<String>,
+    length: u64,
+}
+
+// Subidas en curso en `data_dir/tus`. El avance de cada una es el tamaño del
+// archivo parcial en disco.
+#[derive(Clone)]
+pub struct TusUploads {
+    dir: PathBuf,
+    // Subidas que están recibiendo un PATCH; tus no admite dos a la vez.
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+impl TusUploads {
+    pub fn new(dir: PathBuf) -> Self {
+        TusUploads { dir, active: Arc::default() }
+    }
+
+    // Los IDs son UUID; cualquier otra cosa no es una subida (ni una ruta válida).
+    fn data_path(&self, id: &str) -> Option<PathBuf> {
+        Uuid::parse_str(id).ok().map(|_| self.dir.join(id))
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    fn load(&self, id: &str) -> Option<(UploadInfo, PathBuf)> {
+        let data = self.data_path(id)?;
+        let info = fs::read(self.info_path(id)).ok()?;
+        Some((serde_json::from_slice(&info).ok()?, data))
+    }
+
+    fn create(&self, info: &UploadInfo) -> io::Result<String> {
+        fs::create_dir_all(&self.dir)?;
+        let id = Uuid::new_v4().to_string();
+        fs::File::create(self.dir.join(&id))?;
+        fs::write(self.info_path(&id), serde_json::to_vec(info)?)?;
+        Ok(id)
+    }
+
+    // Marca la subida como activa mientras dure el guardia; `None` si ya lo estaba.
+    fn start(&self, id: &str) -> Option<ActiveGuard<'_>> {
+        if !self.active.lock().unwrap().insert(id.to_string()) {
+            return None;
+        }
+        Some(ActiveGuard { uploads: self, id: id.to_string() })
+    }
+
+    fn remove(&self, id: &str) {
+        let _ = fs::remove_file(self.info_path(id));
+        if let Some(data) = self.data_path(id) {
+            let _ = fs::remove_file(data);
+        }
+    }
+}
+
+// Libera la subida al terminar el PATCH, también si el cliente corta la conexión
+// y la petición se descarta a mitad de camino.
+struct ActiveGuard<'a> {
+    uploads: &'a TusUploads,
+    id: String,
+}
+
+impl Drop for ActiveGuard<'_> {
+    fn drop(&mut self) {
+        self.uploads.active.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn tus_response(mut builder: HttpResponseBuilder) -> HttpResponseBuilder {
+    builder.insert_header(("Tus-Resumable", TUS_VERSION));
+    builder
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+// Toda petición tus (salvo OPTIONS) debe declarar la versión del protocolo.
+fn check_version(request: &HttpRequest) -> Option<HttpResponse> {
+    match header(request.headers(), "Tus-Resumable") {
+        Some(TUS_VERSION) => None,
+        _ => Some(
+            tus_response(HttpResponse::PreconditionFailed())
+                .insert_header(("Tus-Version", TUS_VERSION))
+                .finish(),
+        ),
+    }
+}
+
+// `Upload-Metadata`: pares `clave valor-en-base64` separados por comas.
+fn parse_metadata(raw: &str) -> Result<Vec<(String, String)>, String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+            let value = base64::engine::general_purpose::STANDARD
+                .decode(value.trim())
+                .map_err(|e| format!("Upload-Metadata inválido en {}: {}", key, e))?;
+            let value = String::from_utf8(value).map_err(|_| format!("Upload-Metadata inválido en {}", key))?;
+            Ok((key.to_string(), value))
+        })
+        .collect()
+}
+
+// OPTIONS /uploads — capacidades del servidor.
+pub async fn options() -> HttpResponse {
+    tus_response(HttpResponse::NoContent())
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS))
+        .finish()
+}
+
+// POST /uploads — crea una subida de `Upload-Length` bytes. `Upload-Metadata`
+// debe incluir `job_id` (un trabajo en `receiving`) y puede incluir `filename` y
+// `filetype`, como los envía tus-js-client.
+pub async fn create(request: HttpRequest, uploads: web::Data<TusUploads>, pipeline: web::Data<Pipeline>) -> HttpResponse {
+    if let Some(response) = check_version(&request) {
+        return response;
+    }
+    let headers = request.headers();
+    let length = match header(headers, "Upload-Length").and_then(|value| value.parse::<u64>().ok()) {
+        Some(length) => length,
+        None => return tus_response(HttpResponse::BadRequest()).body("Falta Upload-Length"),
+    };
+    let metadata = match parse_metadata(header(headers, "Upload-Metadata").unwrap_or_default()) {
+        Ok(metadata) => metadata,
+        Err(err) => return tus_response(HttpResponse::BadRequest()).body(err),
+    };
+    let value = |key: &str| metadata.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+
+    let job_id = match value("job_id") {
+        Some(job_id) => job_id,
+        None => return tus_response(HttpResponse::BadRequest()).body("Falta job_id en Upload-Metadata"),
+    };
+    match pipeline.jobs.get(&job_id) {
+        Some(job) if matches!(job.state, JobState::Receiving { .. }) => {}
+        Some(_) => return tus_response(HttpResponse::Conflict()).body("El trabajo ya no recibe imágenes"),
+        None => return tus_response(HttpResponse::NotFound()).body("Trabajo no encontrado"),
+    }
+
+    let info = UploadInfo { job_id, filename: value("filename").unwrap_or_default(), filetype: value("filetype"), length };
+    match uploads.create(&info) {
+        Ok(id) => tus_response(HttpResponse::Created())
+            .insert_header(("Location", format!("/uploads/{}", id)))
+            .finish(),
+        Err(err) => tus_response(HttpResponse::InternalServerError()).body(format!("Error al crear la subida: {}", err)),
+    }
+}
+
+// HEAD /uploads/{id} — cuántos bytes ya se recibieron, para reanudar.
+pub async fn head(request: HttpRequest, uploads: web::Data<TusUploads>, path: web::Path<String>) -> HttpResponse {
+    if let Some(response) = check_version(&request) {
+        return response;
+    }
+    let (info, data) = match uploads.load(&path.into_inner()) {
+        Some(upload) => upload,
+        None => return tus_response(HttpResponse::NotFound()).finish(),
+    };
+    match fs::metadata(&data) {
+        Ok(metadata) => tus_response(HttpResponse::Ok())
+            .insert_header(("Upload-Offset", metadata.len().to_string()))
+            .insert_header(("Upload-Length", info.length.to_string()))
+            .insert_header(("Cache-Control", "no-store"))
+            .finish(),
+        Err(_) => tus_response(HttpResponse::NotFound()).finish(),
+    }
+}
+
+// PATCH /uploads/{id} — agrega bytes a partir de `Upload-Offset`. Lo recibido se
+// escribe en disco a medida que llega, así que si la conexión se corta el cliente
+// continúa desde lo que indique HEAD.
+pub async fn patch(
+    request: HttpRequest,
+    mut payload: web::Payload,
+    uploads: web::Data<TusUploads>,
+    pipeline: web::Data<Pipeline>,
+    path: web::Path<String>,
+) -> HttpResponse {
+    if let Some(response) = check_version(&request) {
+        return response;
+    }
+    let id = path.into_inner();
+    let headers = request.headers();
+    if header(headers, "Content-Type") != Some(OFFSET_CONTENT_TYPE) {
+        return tus_response(HttpResponse::UnsupportedMediaType()).finish();
+    }
+    let offset = match header(headers, "Upload-Offset").and_then(|value| value.parse::<u64>().ok()) {
+        Some(offset) => offset,
+        None => return tus_response(HttpResponse::BadRequest()).body("Falta Upload-Offset"),
+    };
+    let (info, data) = match uploads.load(&id) {
+        Some(upload) => upload,
+        None => return tus_response(HttpResponse::NotFound()).finish(),
+    };
+    if !pipeline.jobs.get(&info.job_id).is_some_and(|job| matches!(job.state, JobState::Receiving { .. })) {
+        return tus_response(HttpResponse::Conflict()).body("El trabajo ya no recibe imágenes");
+    }
+    let _active = match uploads.start(&id) {
+        Some(guard) => guard,
+        None => return tus_response(HttpResponse::Conflict()).body("La subida ya está recibiendo datos"),
+    };
+    let offset = match append(&data, offset, info.length, &mut payload).await {
+        Ok(offset) => offset,
+        Err(response) => return response,
+    };
+    if offset == info.length {
+        // Si no se pudo guardar la imagen, la subida completa se conserva y el
+        // cliente puede reintentar con un PATCH vacío.
+        if let Some(response) = attach(&pipeline, &uploads, &id, &info, &data) {
+            return response;
+        }
+        uploads.remove(&id);
+    }
+    tus_response(HttpResponse::NoContent()).insert_header(("Upload-Offset", offset.to_string())).finish()
+}
+
+// Escribe el cuerpo al final del archivo parcial y devuelve el nuevo avance.
+async fn append(data: &Path, offset: u64, length: u64, payload: &mut web::Payload) -> Result<u64, HttpResponse> {
+    let error = |err: io::Error| {
+        tus_response(HttpResponse::InternalServerError()).body(format!("Error al guardar la subida: {}", err))
+    };
+    let mut file = tokio::fs::OpenOptions::new().append(true).open(data).await.map_err(error)?;
+    let mut current = file.metadata().await.map_err(error)?.len();
+    if current != offset {
+        return Err(tus_response(HttpResponse::Conflict())
+            .insert_header(("Upload-Offset", current.to_string()))
+            .body("Upload-Offset no coincide con lo recibido"));
+    }
+
+    while let Some(chunk) = payload.next().await {
+        // Si la conexión se corta, lo ya escrito queda para reanudar.
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(_) => break,
+        };
+        if current + chunk.len() as u64 > length {
+            return Err(tus_response(HttpResponse::PayloadTooLarge()).body("Se excede Upload-Length"));
+        }
+        file.write_all(&chunk).await.map_err(error)?;
+        current += chunk.len() as u64;
+    }
+    file.flush().await.map_err(error)?;
+    Ok(current)
+}
+
+// Mueve el archivo completo a las imágenes del trabajo y, si era el último,
+// encola el trabajo. Devuelve la respuesta de error si no se pudo agregar.
+fn attach(pipeline: &Pipeline, uploads: &TusUploads, id: &str, info: &UploadInfo, data: &Path) -> Option<HttpResponse> {
+    let filename = Some(info.filename.as_str()).filter(|name| !name.is_empty());
+    let image = match pipeline.add_image(&info.job_id, data, filename, info.filetype.as_deref()) {
+        Ok(image) => image,
+        Err(err) => {
+            return Some(
+                tus_response(HttpResponse::InternalServerError()).body(format!("Error al guardar la imagen: {}", err)),
+            )
+        }
+    };
+
+    let name = image.name.clone();
+    match pipeline.jobs.receive_image(&info.job_id, image) {
+        Some(true) => {
+            println!("El trabajo {} recibió todas sus imágenes", info.job_id);
+            tokio::spawn(pipeline.clone().run(info.job_id.clone()));
+            None
+        }
+        Some(false) => None,
+        // El trabajo se confirmó o canceló mientras llegaba el archivo. Los datos
+        // ya se movieron a las imágenes, así que la subida no se puede reintentar.
+        None => {
+            let _ = fs::remove_file(pipeline.images_dir(&info.job_id).join(name));
+            uploads.remove(id);
+            Some(tus_response(HttpResponse::Conflict()).body("El trabajo ya no recibe imágenes"))
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::jobs::{Job, JobStore};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::Path;
+use std::time::Duration;
+
+// Encabezado con la firma del cuerpo: `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    // URLs que reciben el aviso de todos los trabajos, además del `callback_url` de cada uno.
+    pub urls: Vec<String>,
+    // Secreto compartido para firmar el cuerpo con HMAC-SHA256; sin él no se firma.
+    pub secret: Option<String>,
+    pub max_attempts: u32,
+    // Espera antes del primer reintento; se duplica en cada intento.
+    pub initial_backoff_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig { urls: Vec::new(), secret: None, max_attempts: 5, initial_backoff_secs: 2, timeout_secs: 10 }
+    }
+}
+
+// Intento de entrega de un webhook, visible en el trabajo.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookDelivery {
+    pub url: String,
+    pub attempt: u32,
+    pub at: DateTime<Utc>,
+    // Código HTTP de la respuesta, si la hubo.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+// Cuerpo que se envía cuando un trabajo termina.
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    job_id: &'a str,
+    state: &'static str,
+    artifacts: Vec<String>,
+    error: Option<&'a str>,
+    at: DateTime<Utc>,
+}
+
+// Envía los avisos de fin de trabajo a su `callback_url` y a las URLs globales.
+#[derive(Clone)]
+pub struct Webhooks {
+    config: WebhookConfig,
+    jobs: JobStore,
+    // URL con la que los clientes llegan al servicio, para armar las URLs de los resultados.
+    public_url: String,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig, jobs: JobStore, public_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .unwrap_or_default();
+        Webhooks { config, jobs, public_url: public_url.trim_end_matches('/').to_string(), client }
+    }
+
+    // Lanza en segundo plano la entrega del aviso de fin del trabajo.
+    pub fn job_finished(&self, job_id: &str) {
+        let job = match self.jobs.get(job_id) {
+            Some(job) => job,
+            None => return,
+        };
+        let urls: Vec<String> = job.callback_url.iter().chain(&self.config.urls).cloned().collect();
+        if urls.is_empty() {
+            return;
+        }
+        let body = match serde_json::to_vec(&self.payload(&job)) {
+            Ok(body) => body,
+            Err(err) => {
+                println!("Error al preparar el webhook del trabajo {}: {}", job_id, err);
+                return;
+            }
+        };
+        for url in urls {
+            tokio::spawn(self.clone().deliver(job.id.clone(), url, body.clone()));
+        }
+    }
+
+    fn payload<'a>(&self, job: &'a Job) -> WebhookPayload<'a> {
+        let artifacts = job
+            .artifacts
+            .iter()
+            .filter_map(|path| Path::new(path).file_name())
+            .map(|name| format!("{}/jobs/{}/artifacts/{}", self.public_url, job.id, name.to_string_lossy()))
+            .collect();
+        WebhookPayload { job_id: &job.id, state: job.state.name(), artifacts, error: job.error.as_deref(), at: Utc::now() }
+    }
+
+    // Envía el cuerpo hasta que la URL responda 2xx, con espera exponencial entre
+    // intentos. Cada intento se registra en el trabajo.
+    async fn deliver(self, job_id: String, url: String, body: Vec<u8>) {
+        let mut backoff = Duration::from_secs(self.config.initial_backoff_secs);
+        for attempt in 1..=self.config.max_attempts {
+            let mut request = self.client.post(&url).header("Content-Type", "application/json");
+            if let Some(secret) = &self.config.secret {
+                request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+            }
+            let (status, error) = match request.body(body.clone()).send().await {
+                Ok(resp) if resp.status().is_success() => (Some(resp.status().as_u16()), None),
+                Ok(resp) => (Some(resp.status().as_u16()), Some(format!("respuesta {}", resp.status()))),
+                Err(err) => (None, Some(err.to_string())),
+            };
+            let delivered = error.is_none();
+            if let Some(err) = &error {
+                println!("Webhook {} del trabajo {} falló (intento {}): {}", url, job_id, attempt, err);
+            }
+            self.jobs.add_delivery(
+                &job_id,
+                WebhookDelivery { url: url.clone(), attempt, at: Utc::now(), status, error, delivered },
+            );
+            if delivered {
+                return;
+            }
+            if attempt < self.config.max_attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+    }
+}
+
+// Firma HMAC-SHA256 del cuerpo en hexadecimal.
+fn sign(secret: &str, body: &[u8]) -> String {
+    // HMAC acepta claves de cualquier longitud.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("clave HMAC");
+    mac.update(body);
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}